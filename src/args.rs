@@ -2,34 +2,213 @@
  * Copyright (c) 2020 Michael Richter <mr@osor.de>
  */
 
-//! Parse and validate command line arguments.
+//! Parse and validate command line arguments and configuration files.
 
-use crate::logfile::{Pattern, ProblemType, file_modified};
+use crate::logfile::{
+    Correlation, CountPattern, ExcludePattern, ExpectPattern, MatchPolicy, OutputMode, Pattern, ProblemType, RulePatterns, Rules,
+    ValuePattern, file_modified,
+};
+use crate::maintenance::{MaintenanceTarget, MaintenanceWindow};
+use crate::preset::{self, LINE_PRESETS};
+use crate::state::State;
+use crate::stats::{StatThreshold, STAT_NAMES};
+use crate::threshold::Thresholds;
+use chrono::{prelude::*, Duration};
+use clap::{App, ArgMatches};
 use directories::ProjectDirs;
 use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::env::temp_dir;
-use std::fs::read_dir;
-use std::path::PathBuf;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 /// Processed and transformed command line arguments.
 pub struct Args {
+    /// Groups of log file sets.
+    pub groups: Vec<FileGroup>,
+
+    /// Regular expressions to search for in groups without own problem patterns.
+    pub rules: Rules,
+
+    /// The part of messages to be printed.
+    pub output: OutputMode,
+
+    /// Thresholds for statistics of captured values.
+    pub stat_thresholds: Vec<StatThreshold>,
+
+    /// Calculate statistics of captured values over this amount of seconds across runs.
+    pub value_window: Option<i64>,
+
+    /// Thresholds for the seconds since the last modification of the log file sets.
+    pub max_ages: Vec<MaxAge>,
+
+    /// Windows in which problems are counted but do not raise the state.
+    pub maintenance: Vec<MaintenanceWindow>,
+
+    /// Raise this problem for messages with unknown fingerprints and learn them. Messages with
+    /// learned fingerprints are counted only.
+    pub learn: Option<ProblemType>,
+
+    /// The path to the state file.
+    pub state_path: PathBuf,
+
+    /// Keep WARNING and CRITICAL status for this amount of seconds.
+    pub keep_status: i64,
+
+    /// The name of the check from the configuration file.
+    pub check: Option<String>,
+}
+
+/// Log file sets sharing a line pattern and problem patterns.
+pub struct FileGroup {
     /// List of log file sets.
     pub files: Vec<Files>,
 
     /// Regular expression pattern to determine a message start.
     pub line_re: Regex,
 
-    /// List of regular expressions to search for.
-    pub patterns: Vec<Pattern>,
+    /// Regular expression pattern to determine the last line of a message.
+    pub end_re: Option<Regex>,
+
+    /// Regular expression pattern to determine the lines continuing a message. Other lines start a new message.
+    pub continuation_re: Option<Regex>,
+
+    /// Regular expressions to search for if the group has own problem patterns.
+    pub rules: Option<Rules>,
+}
+
+/// Raw options of a check given on the command line or in the configuration file.
+/// The names equal the long command line options.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Options {
+    file: Vec<String>,
+    line: Option<String>,
+    linepreset: Option<String>,
+    end: Option<String>,
+    warningpattern: Vec<String>,
+    criticalpattern: Vec<String>,
+    warningpatternfile: Vec<String>,
+    criticalpatternfile: Vec<String>,
+    unknownpattern: Vec<String>,
+    countpattern: Vec<String>,
+    ratepattern: Vec<String>,
+    infopattern: Vec<String>,
+    valuepattern: Vec<String>,
+    valuestat: Vec<String>,
+    valuewindow: Option<String>,
+    excludepattern: Vec<String>,
+    okpattern: Vec<String>,
+    expectpattern: Vec<String>,
+    expectstatus: Option<String>,
+    maxage: Vec<String>,
+    correlate: Vec<Vec<String>>,
+    correlatestatus: Option<String>,
+    warningexcludepattern: Vec<String>,
+    criticalexcludepattern: Vec<String>,
+    matchpolicy: Option<String>,
+    output: Option<String>,
+    maintenance: Vec<String>,
+    learn: Option<String>,
+    statefile: Option<String>,
+    keepstatus: Option<String>,
+    namedpatterns: bool,
+    group: Vec<GroupOptions>,
+    #[serde(skip)]
+    indices: HashMap<String, Vec<usize>>,
+}
+
+/// The options of problem patterns whose order on the command line is kept.
+const PATTERN_OPTIONS: [&str; 5] = [
+    "warningpattern",
+    "warningpatternfile",
+    "criticalpattern",
+    "criticalpatternfile",
+    "unknownpattern",
+];
+
+/// The options without value. A '--group' following any other option without inline value is the
+/// value of this option.
+const FLAG_OPTIONS: [&str; 6] = ["--group", "--namedpatterns", "-h", "--help", "-V", "--version"];
+
+/// Raw options of a file group given on the command line after '--group' or in the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GroupOptions {
+    file: Vec<String>,
+    line: Option<String>,
+    linepreset: Option<String>,
+    end: Option<String>,
+    warningpattern: Vec<String>,
+    criticalpattern: Vec<String>,
+    warningpatternfile: Vec<String>,
+    criticalpatternfile: Vec<String>,
+    unknownpattern: Vec<String>,
+    #[serde(skip)]
+    indices: HashMap<String, Vec<usize>>,
+}
+
+/// A configuration file describing multiple checks.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// The path to the state file shared by all checks.
+    statefile: Option<String>,
+
+    /// The checks by name.
+    checks: HashMap<String, Options>,
+}
+
+/// The command given on the command line.
+pub enum Command {
+    /// Check log files.
+    Check(Box<Args>),
+
+    /// List or change the states in the state file. Invalid arguments of the subcommand are no
+    /// check result, they are kept to fail the subcommand.
+    State(Result<StateArgs, String>),
+}
+
+/// Processed command line arguments of the state subcommand.
+pub struct StateArgs {
+    /// The action to perform on the states.
+    pub action: StateAction,
 
     /// The path to the state file.
     pub state_path: PathBuf,
 
-    /// Keep WARNING and CRITICAL status for this amount of seconds.
-    pub keep_status: i64,
+    /// Restrict the action to the states of this check.
+    pub check: Option<String>,
+
+    /// Restrict the action to the states of these log files.
+    pub files: Vec<PathBuf>,
+}
+
+/// An action of the state subcommand.
+pub enum StateAction {
+    /// List the learned messages.
+    List,
+
+    /// Forget learned messages by fingerprint. All learned messages are forgotten without fingerprints.
+    Forget(Vec<String>),
+
+    /// Acknowledge kept messages to remove them from the state. All kept messages are acknowledged
+    /// without fingerprints and date.
+    Ack {
+        /// Acknowledge the kept messages with these fingerprints only.
+        fingerprints: Vec<String>,
+
+        /// Acknowledge the kept messages found before this date only.
+        before: Option<DateTime<Utc>>,
+    },
 }
 
+/// A tuple containing the thresholds for the seconds since the last modification of a log file
+/// and the path of the log file set they apply to. Thresholds without path apply to all file sets.
+pub type MaxAge = (Thresholds, Option<PathBuf>);
+
 /// A file set containing the main log file with index 0 and possible rotated log files following ordered by its creating date.
 pub type Files = Vec<PathBuf>;
 
@@ -37,27 +216,747 @@ pub type Files = Vec<PathBuf>;
 type FilesTime = Vec<(PathBuf, SystemTime)>;
 
 impl Args {
-    /// Parse, validate and transform the command line arguments.
-    pub fn get() -> Result<Self, String> {
-        let args = clap_app!(app => (name: env!("CARGO_PKG_NAME"))
+    /// Get the rules to search the log files of a group with.
+    /// # Arguments
+    /// * `group` - The file group
+    pub fn group_rules<'a>(&'a self, group: &'a FileGroup) -> &'a Rules {
+        group.rules.as_ref().unwrap_or(&self.rules)
+    }
+
+    /// Get all log file sets with their group and the rules to search them with.
+    pub fn file_sets(&self) -> impl Iterator<Item = (&Files, &FileGroup, &Rules)> {
+        self.groups.iter().flat_map(move |group| {
+            group
+                .files
+                .iter()
+                .map(move |files| (files, group, self.group_rules(group)))
+        })
+    }
+
+    /// Tests if a log file set belongs to any group.
+    /// # Arguments
+    /// * `path` - The path to the main log file
+    pub fn contains_file(&self, path: &Path) -> bool {
+        self.groups.iter().flat_map(|group| &group.files).any(|files| files[0] == path)
+    }
+
+    /// Get the thresholds for the seconds since the last modification of a log file set.
+    /// Thresholds for the file set replace the thresholds for all file sets.
+    /// # Arguments
+    /// * `files` - The log file set
+    pub fn max_age(&self, files: &Files) -> Option<&Thresholds> {
+        self.max_ages
+            .iter()
+            .rev()
+            .find(|max_age| max_age.1.as_ref() == Some(&files[0]))
+            .or_else(|| self.max_ages.iter().rev().find(|max_age| max_age.1.is_none()))
+            .map(|max_age| &max_age.0)
+    }
+}
+
+impl Command {
+    /// Define the command line arguments.
+    /// # Arguments
+    /// * `preset_names` - The names of the line presets
+    fn app<'a>(preset_names: &'a [&'a str]) -> App<'a, 'a> {
+        clap_app!(app => (name: env!("CARGO_PKG_NAME"))
             (version: env!("CARGO_PKG_VERSION"))
             (author: env!("CARGO_PKG_AUTHORS"))
             (about: env!("CARGO_PKG_DESCRIPTION"))
-            (@arg file: -f --file +takes_value +required +multiple "Log file to analyze. Append '#<rotatenamepattern>' to specify rotated files.")
+            (@arg config: --config +takes_value requires[check] "TOML configuration file describing multiple checks")
+            (@arg check: --check +takes_value requires[config] "Name of the check in the configuration file")
+            (@arg file: -f --file +takes_value required_unless_one(&["config", "group"]) +multiple "Log file to analyze. Append '#<rotatenamepattern>' to specify rotated files.")
             (@arg linepattern: -l --line +takes_value "Pattern to detect new lines")
-            (@arg warningpattern: -w --warningpattern +takes_value +multiple "Regex pattern to trigger a WARNING problem")
-            (@arg criticalpattern: -c --criticalpattern +takes_value +multiple "Regex pattern to trigger a CRITICAL problem")
+            (@arg linepreset: --linepreset alias("line-preset") +takes_value possible_values(&preset_names) conflicts_with[linepattern] "Built-in pattern to detect new lines of a common log format")
+            (@arg endpattern: --end +takes_value "Pattern to detect the last line of a message")
+            (@arg warningpattern: -w --warningpattern +takes_value +multiple "Regex pattern to trigger a WARNING problem. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg criticalpattern: -c --criticalpattern +takes_value +multiple "Regex pattern to trigger a CRITICAL problem. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg warningpatternfile: --warningpatternfile +takes_value +multiple "File with regex patterns to trigger a WARNING problem, one per line")
+            (@arg criticalpatternfile: --criticalpatternfile +takes_value +multiple "File with regex patterns to trigger a CRITICAL problem, one per line")
+            (@arg unknownpattern: --unknownpattern +takes_value +multiple "Regex pattern to trigger an UNKNOWN problem for messages making the monitoring unreliable. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg countpattern: -t --countpattern +takes_value +multiple "Regex pattern with count thresholds as 'WARNRANGE,CRITRANGE,PATTERN'")
+            (@arg ratepattern: --ratepattern +takes_value +multiple "Regex pattern with count thresholds for a time window across runs as 'WINDOW,WARNRANGE,CRITRANGE,PATTERN'")
+            (@arg infopattern: --infopattern +takes_value +multiple "Regex pattern to count messages for performance data only. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg valuepattern: --valuepattern +takes_value +multiple "Regex pattern capturing a number in the group 'value' or the first group with thresholds as 'WARNRANGE,CRITRANGE,PATTERN'")
+            (@arg valuestat: --valuestat +takes_value +multiple "Thresholds for a statistic of the values of a value pattern as 'STAT,WARNRANGE,CRITRANGE,NAME'. STAT is count, min, max, avg, sum, p50, p90 or p99.")
+            (@arg valuewindow: --valuewindow +takes_value "Calculate statistics of values over this duration across runs")
+            (@arg excludepattern: -x --excludepattern +takes_value +multiple "Regex pattern to ignore messages matching any WARNING or CRITICAL pattern")
+            (@arg warningexcludepattern: --warningexcludepattern +takes_value +multiple "Regex pattern to ignore messages matching a WARNING pattern")
+            (@arg criticalexcludepattern: --criticalexcludepattern +takes_value +multiple "Regex pattern to ignore messages matching a CRITICAL pattern")
+            (@arg okpattern: -o --okpattern +takes_value +multiple "Regex pattern to clear previous and kept problems. A named pattern clears problems of the patterns with the same name. A capture group 'key' clears problems with the same captured key only.")
+            (@arg expectpattern: --expectpattern +takes_value +multiple "Regex pattern expected to match a message within a duration as 'DURATION,PATTERN'")
+            (@arg expectstatus: --expectstatus +takes_value possible_value[warning critical] "The problem raised for missing matches of expect patterns (default: critical)")
+            (@arg correlate: --correlate +takes_value +multiple number_of_values(3) value_names(&["LIMIT", "START", "END"]) "Raise a problem if a message matching the START pattern is not followed by a message matching the END pattern with the same key within LIMIT. The key is captured by the group 'key' or the first group. LIMIT is a count of messages, a duration with unit or both as 'MESSAGES/DURATION'.")
+            (@arg correlatestatus: --correlatestatus +takes_value possible_value[warning critical] "The problem raised for missing ends of correlations (default: critical)")
+            (@arg maxage: --maxage +takes_value +multiple "Durations since the last modification of log files to raise a WARNING or CRITICAL as 'WARNAGE,CRITAGE'. Append ',FILE' to apply them to a single log file.")
+            (@arg matchpolicy: --matchpolicy +takes_value possible_value[highest first] "Use the pattern with the highest severity or the first matching pattern in command line order to classify a message (default: highest)")
+            (@arg output: --output +takes_value possible_value[full firstline captures json] "Print the whole messages, their first lines or matching lines, the capture groups of the matching patterns or the whole messages as JSON (default: full)")
+            (@arg maintenance: --maintenance +takes_value +multiple "Weekly window as '[DAYS] HH:MM-HH:MM [TIMEZONE]' in which problems are counted but do not raise the state. Append ',pattern:NAME' or ',file:FILE' to apply it to a pattern or log file only. Windows are active by the time of the check run.")
+            (@arg learn: --learn +takes_value possible_value[warning critical] "Raise this problem for messages never seen before and learn them. Learned messages are counted only.")
             (@arg statefile: -s --statefile +takes_value "File to save the processing state in from run to run")
             (@arg keepstatus: -k --keepstatus +takes_value "Remember WARNINGs and CRITICALs for this duration")
-        ).get_matches();
+            (@arg namedpatterns: --namedpatterns "Parse a leading 'NAME=', 'NAME/FLAGS=' or '/FLAGS=' of all patterns as name and flags [imsxlfe] of the pattern. A leading '=' keeps a pattern containing '=' unnamed.")
+            (@arg group: --group "Start a group of log files with an own line pattern and own problem patterns. The group takes the options up to the next '--group'. The problem patterns given before the first group apply to all groups, the problem patterns of a group are searched in addition. All other patterns apply to all groups.")
+            (@setting SubcommandsNegateReqs)
+            (@subcommand state =>
+                (about: "List or change the states in the state file")
+                (@setting SubcommandRequiredElseHelp)
+                (@arg statefile: -s --statefile +takes_value +global "State file to work on")
+                (@arg config: --config +takes_value +global "TOML configuration file defining the state file")
+                (@arg check: --check +takes_value +global "Restrict to the states of the check")
+                (@arg file: -f --file +takes_value +multiple +global "Restrict to the states of the log file")
+                (@subcommand list =>
+                    (about: "List the kept and learned messages"))
+                (@subcommand forget =>
+                    (about: "Forget learned messages")
+                    (@arg fingerprint: --fingerprint +takes_value +multiple required_unless("all") "Fingerprint of the message to forget")
+                    (@arg all: --all "Forget all learned messages"))
+                (@subcommand ack =>
+                    (about: "Acknowledge kept messages to clear their problems")
+                    (@arg fingerprint: --fingerprint +takes_value +multiple required_unless_one(&["before", "all"]) "Fingerprint of the kept message to acknowledge")
+                    (@arg before: --before +takes_value "Acknowledge kept messages found before this RFC 3339 date or duration ago only")
+                    (@arg all: --all conflicts_with[fingerprint before] "Acknowledge all kept messages"))
+            )
+        )
+    }
+
+    /// Parse, validate and transform the command line arguments.
+    /// Options of a check from a configuration file are extended by the command line options.
+    pub fn get() -> Result<Self, String> {
+        // Split the options of file groups starting with '--group' from the check options
+        let (mut argv, group_args) = split_group_args(std::env::args().collect());
+        let groups: Vec<GroupOptions> = group_args.into_iter().map(GroupOptions::from_args).collect();
+        if !groups.is_empty() {
+            argv.push(String::from("--group"));
+        }
+
+        let preset_names: Vec<&str> = LINE_PRESETS.iter().map(|preset| preset.0).collect();
+        let args = Command::app(&preset_names).get_matches_from(argv);
+
+        if let Some(state_args) = args.subcommand_matches("state") {
+            return Ok(Command::State(StateArgs::from_matches(state_args)));
+        }
+
+        let mut options = Options::from_matches(&args);
+        options.group = groups;
+        let check = args.value_of("check").map(String::from);
+
+        // config
+        if let Some(path) = args.value_of("config") {
+            let name = check.as_deref().unwrap_or_default();
+            options = Config::load(path)?
+                .check_options(name, options)
+                .ok_or(format!("No check '{}' in configuration file '{}'", name, path))?;
+        }
+
+        Args::from_options(options, check).map(|args| Command::Check(Box::new(args)))
+    }
+}
+
+impl Args {
+    /// Validate and transform the options of a check.
+    /// # Arguments
+    /// * `options` - The raw options
+    /// * `check` - The name of the check from the configuration file
+    fn from_options(options: Options, check: Option<String>) -> Result<Self, String> {
+        // namedpatterns
+        let named = options.namedpatterns;
+
+        // file, linepattern, endpattern and problem patterns of all groups
+        let common = GroupOptions {
+            file: options.file,
+            line: options.line,
+            linepreset: options.linepreset,
+            end: options.end,
+            warningpattern: options.warningpattern,
+            criticalpattern: options.criticalpattern,
+            warningpatternfile: options.warningpatternfile,
+            criticalpatternfile: options.criticalpatternfile,
+            unknownpattern: options.unknownpattern,
+            indices: options.indices,
+        };
+        if common.file.is_empty() && options.group.is_empty() {
+            return Err(String::from("No file argument given."));
+        }
+        let linepattern = common.line_pattern()?;
+        let continuationpattern = common.continuation_pattern();
+        let endpattern = common.end.as_deref();
+        let patterns = common.patterns(named)?;
+        let mut groups: Vec<FileGroup> = vec![];
+        let mut group_patterns: Vec<Vec<Pattern>> = vec![];
+        if !common.file.is_empty() {
+            groups.push(FileGroup {
+                files: common.files()?,
+                line_re: parse_line(linepattern, endpattern)?,
+                end_re: parse_end(endpattern)?,
+                continuation_re: parse_continuation(continuationpattern)?,
+                rules: None,
+            });
+            group_patterns.push(vec![]);
+        }
+        for (index, group) in options.group.iter().enumerate() {
+            if group.file.is_empty() {
+                return Err(format!("No file argument given for group {}.", index + 1));
+            }
+            let group_linepattern = group.line_pattern()?;
+            let group_continuationpattern = match group_linepattern {
+                Some(_) => group.continuation_pattern(),
+                None => continuationpattern,
+            };
+            let group_linepattern = group_linepattern.or(linepattern);
+            let group_endpattern = group.end.as_deref().or(endpattern);
+            groups.push(FileGroup {
+                files: group.files()?,
+                line_re: parse_line(group_linepattern, group_endpattern)?,
+                end_re: parse_end(group_endpattern)?,
+                continuation_re: parse_continuation(group_continuationpattern)?,
+                rules: None,
+            });
+            group_patterns.push(group.patterns(named)?);
+        }
+
+        // countpattern
+        let mut count_patterns: Vec<CountPattern> = vec![];
+        for pattern in &options.countpattern {
+            let parts: Vec<&str> = pattern.splitn(3, ',').collect();
+            if parts.len() < 3 {
+                return Err(String::from(
+                    "Invalid count pattern. Use 'WARNRANGE,CRITRANGE,PATTERN'.",
+                ));
+            }
+            let thresholds = Thresholds::parse(parts[0], parts[1])?;
+            match parse_pattern(parts[2], ProblemType::OK, named) {
+                Ok(pattern) => count_patterns.push((thresholds, None, pattern)),
+                Err(e) => return Err(format!("Invalid count pattern: {}", e)),
+            };
+        }
+
+        // ratepattern
+        for pattern in &options.ratepattern {
+            let parts: Vec<&str> = pattern.splitn(4, ',').collect();
+            if parts.len() < 4 {
+                return Err(String::from(
+                    "Invalid rate pattern. Use 'WINDOW,WARNRANGE,CRITRANGE,PATTERN'.",
+                ));
+            }
+            let window = parse_duration(parts[0])
+                .map_err(|e| format!("Invalid rate pattern window: {}", e))?;
+            let thresholds = Thresholds::parse(parts[1], parts[2])?;
+            match parse_pattern(parts[3], ProblemType::OK, named) {
+                Ok(pattern) => count_patterns.push((thresholds, Some(window), pattern)),
+                Err(e) => return Err(format!("Invalid rate pattern: {}", e)),
+            };
+        }
+
+        // infopattern
+        for pattern in &options.infopattern {
+            match parse_pattern(pattern, ProblemType::OK, named) {
+                Ok(pattern) => count_patterns.push((Thresholds::default(), None, pattern)),
+                Err(e) => return Err(format!("Invalid info pattern: {}", e)),
+            };
+        }
+
+        // valuepattern
+        let mut value_patterns: Vec<ValuePattern> = vec![];
+        for pattern in &options.valuepattern {
+            let parts: Vec<&str> = pattern.splitn(3, ',').collect();
+            if parts.len() < 3 {
+                return Err(String::from(
+                    "Invalid value pattern. Use 'WARNRANGE,CRITRANGE,PATTERN'.",
+                ));
+            }
+            let thresholds = Thresholds::parse(parts[0], parts[1])?;
+            match parse_pattern(parts[2], ProblemType::OK, named) {
+                Ok(pattern) if pattern.re.captures_len() > 1 => {
+                    value_patterns.push((thresholds, pattern))
+                }
+                Ok(_) => return Err(String::from("Value pattern has no capture group.")),
+                Err(e) => return Err(format!("Invalid value pattern: {}", e)),
+            };
+        }
+
+        // valuestat
+        let mut stat_thresholds: Vec<StatThreshold> = vec![];
+        for stat in &options.valuestat {
+            let parts: Vec<&str> = stat.splitn(4, ',').collect();
+            if parts.len() < 4 {
+                return Err(String::from(
+                    "Invalid value statistic. Use 'STAT,WARNRANGE,CRITRANGE,NAME'.",
+                ));
+            }
+            if !STAT_NAMES.contains(&parts[0]) {
+                return Err(format!("Unknown value statistic '{}'", parts[0]));
+            }
+            if !value_patterns.iter().any(|pattern| pattern.1.label() == parts[3]) {
+                return Err(format!("No value pattern named '{}'", parts[3]));
+            }
+            let thresholds = Thresholds::parse(parts[1], parts[2])?;
+            stat_thresholds.push((parts[0].to_string(), thresholds, parts[3].to_string()));
+        }
+
+        // valuewindow
+        let value_window = match options.valuewindow.as_deref() {
+            Some(value) => Some(
+                parse_duration(value)
+                    .map_err(|e| format!("Value for valuewindow has invalid format: {}", e))?,
+            ),
+            None => None,
+        };
 
-        // file
-        let files_arg: Vec<&str> = args
-            .values_of("file")
-            .ok_or(String::from("No file argument given."))?
+        // excludepattern
+        let mut excludes: Vec<ExcludePattern> = vec![];
+        let exclude_args = [
+            (&options.excludepattern, None),
+            (&options.warningexcludepattern, Some(ProblemType::WARNING)),
+            (&options.criticalexcludepattern, Some(ProblemType::CRITICAL)),
+        ];
+        for (values, problem_type) in exclude_args.iter() {
+            for pattern in values.iter() {
+                match Regex::new(pattern) {
+                    Ok(re) => excludes.push((*problem_type, re)),
+                    Err(e) => return Err(format!("Invalid exclude pattern: {}", e)),
+                };
+            }
+        }
+
+        // okpattern
+        let mut ok_patterns: Vec<Pattern> = vec![];
+        for pattern in &options.okpattern {
+            match parse_pattern(pattern, ProblemType::OK, named) {
+                Ok(pattern) => ok_patterns.push(pattern),
+                Err(e) => return Err(format!("Invalid ok pattern: {}", e)),
+            };
+        }
+
+        // expectstatus
+        let expect_status = match options.expectstatus.as_deref() {
+            None | Some("critical") => ProblemType::CRITICAL,
+            Some("warning") => ProblemType::WARNING,
+            Some(value) => return Err(format!("Invalid expect status '{}'", value)),
+        };
+
+        // expectpattern
+        let mut expect_patterns: Vec<ExpectPattern> = vec![];
+        for pattern in &options.expectpattern {
+            let parts: Vec<&str> = pattern.splitn(2, ',').collect();
+            if parts.len() < 2 {
+                return Err(String::from("Invalid expect pattern. Use 'DURATION,PATTERN'."));
+            }
+            let within = parse_duration(parts[0])
+                .map_err(|e| format!("Invalid expect pattern duration: {}", e))?;
+            match parse_pattern(parts[1], expect_status, named) {
+                Ok(pattern) => expect_patterns.push((within, pattern)),
+                Err(e) => return Err(format!("Invalid expect pattern: {}", e)),
+            };
+        }
+
+        // correlatestatus
+        let correlate_status = match options.correlatestatus.as_deref() {
+            None | Some("critical") => ProblemType::CRITICAL,
+            Some("warning") => ProblemType::WARNING,
+            Some(value) => return Err(format!("Invalid correlate status '{}'", value)),
+        };
+
+        // correlate
+        let mut correlations: Vec<Correlation> = vec![];
+        for correlate in &options.correlate {
+            if correlate.len() != 3 {
+                return Err(String::from("Invalid correlation. Use 'LIMIT START END'."));
+            }
+            let (messages, window) = parse_limit(&correlate[0])
+                .map_err(|e| format!("Invalid correlation limit: {}", e))?;
+            let parse = |pattern: &str, problem_type: ProblemType| match parse_pattern(pattern, problem_type, named) {
+                Ok(pattern) if pattern.re.captures_len() > 1 => Ok(pattern),
+                Ok(_) => Err(String::from("Correlation pattern has no capture group.")),
+                Err(e) => Err(format!("Invalid correlation pattern: {}", e)),
+            };
+            correlations.push(Correlation {
+                start: parse(&correlate[1], correlate_status)?,
+                end: parse(&correlate[2], ProblemType::OK)?,
+                messages,
+                window,
+            });
+        }
+
+        // maxage
+        let mut max_ages: Vec<MaxAge> = vec![];
+        for max_age in &options.maxage {
+            let parts: Vec<&str> = max_age.splitn(3, ',').collect();
+            if parts.len() < 2 {
+                return Err(String::from(
+                    "Invalid maximum age. Use 'WARNAGE,CRITAGE' or 'WARNAGE,CRITAGE,FILE'.",
+                ));
+            }
+            let seconds = |value: &str| match value {
+                "" => Ok(String::new()),
+                value => parse_duration(value)
+                    .map(|seconds| seconds.to_string())
+                    .map_err(|e| format!("Invalid maximum age: {}", e)),
+            };
+            let thresholds = Thresholds::parse(&seconds(parts[0])?, &seconds(parts[1])?)?;
+            let path = parts.get(2).map(PathBuf::from);
+            if let Some(path) = &path {
+                if !groups.iter().flat_map(|group| &group.files).any(|files| &files[0] == path) {
+                    return Err(format!("No log file '{}' for maximum age", path.to_string_lossy()));
+                }
+            }
+            max_ages.push((thresholds, path));
+        }
+
+        // matchpolicy
+        let match_policy = match options.matchpolicy.as_deref() {
+            None | Some("highest") => MatchPolicy::Highest,
+            Some("first") => MatchPolicy::First,
+            Some(value) => return Err(format!("Invalid match policy '{}'", value)),
+        };
+
+        // output
+        let output = match options.output.as_deref() {
+            None | Some("full") => OutputMode::Full,
+            Some("firstline") => OutputMode::FirstLine,
+            Some("captures") => OutputMode::Captures,
+            Some("json") => OutputMode::Json,
+            Some(value) => return Err(format!("Invalid output mode '{}'", value)),
+        };
+
+        // maintenance
+        let mut maintenance: Vec<MaintenanceWindow> = vec![];
+        for window in &options.maintenance {
+            maintenance.push(MaintenanceWindow::parse(window)?);
+        }
+
+        // learn
+        let learn = match options.learn.as_deref() {
+            None => None,
+            Some("critical") => Some(ProblemType::CRITICAL),
+            Some("warning") => Some(ProblemType::WARNING),
+            Some(value) => return Err(format!("Invalid learn status '{}'", value)),
+        };
+
+        // statefile
+        let statepath = match options.statefile.as_deref() {
+            Some(value) => PathBuf::from(value),
+            None => default_state_path(),
+        };
+
+        // keepstatus
+        let keepstatus: i64 = match options.keepstatus.as_deref() {
+            Some(value) => parse_duration(value)
+                .map_err(|e| format!("Value for keepstatus has invalid format: {}", e))?,
+            None => 0,
+        };
+
+        // Groups with own problem patterns search for them in addition to the common ones
+        let rules = Rules::new(RulePatterns {
+            patterns,
+            count_patterns,
+            value_patterns,
+            excludes,
+            ok_patterns,
+            expect_patterns,
+            correlations,
+            policy: match_policy,
+        })?;
+        for (group, mut own_patterns) in groups.iter_mut().zip(group_patterns) {
+            if !own_patterns.is_empty() {
+                let mut patterns = rules.patterns.patterns.clone();
+                patterns.append(&mut own_patterns);
+                group.rules = Some(rules.with_patterns(patterns)?);
+            }
+        }
+
+        // Maintenance windows apply to the log files and named patterns of the check only
+        let is_file = |path: &str| groups.iter().flat_map(|group| &group.files).any(|files| files[0] == Path::new(path));
+        let is_named = |name: &str| {
+            groups
+                .iter()
+                .filter_map(|group| group.rules.as_ref())
+                .chain(std::iter::once(&rules))
+                .any(|rules| rules.patterns.names().any(|own| own == name))
+        };
+        for window in &maintenance {
+            match &window.target {
+                Some(MaintenanceTarget::File(path)) if !is_file(path) => {
+                    return Err(format!("No log file '{}' for maintenance window", path))
+                }
+                Some(MaintenanceTarget::Pattern(name)) if !is_named(name) => {
+                    return Err(format!("No pattern named '{}' for maintenance window", name))
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Args {
+            groups,
+            rules,
+            output,
+            stat_thresholds,
+            value_window,
+            max_ages,
+            maintenance,
+            learn,
+            state_path: statepath,
+            keep_status: keepstatus,
+            check,
+        })
+    }
+}
+
+impl StateArgs {
+    /// Transform the command line arguments of the state subcommand.
+    /// # Arguments
+    /// * `args` - The parsed command line arguments of the state subcommand
+    fn from_matches(args: &ArgMatches) -> Result<Self, String> {
+        let (name, action_args) = args.subcommand();
+        let action_args = action_args.ok_or(String::from("No state action given."))?;
+        // Global arguments may be given before or after the action
+        let value = |name: &str| action_args.value_of(name).or_else(|| args.value_of(name));
+        let action = match name {
+            "list" => StateAction::List,
+            "forget" => StateAction::Forget(
+                action_args
+                    .values_of_lossy("fingerprint")
+                    .unwrap_or_default(),
+            ),
+            "ack" => StateAction::Ack {
+                fingerprints: action_args
+                    .values_of_lossy("fingerprint")
+                    .unwrap_or_default(),
+                before: match action_args.value_of("before") {
+                    Some(value) => Some(parse_date(value)?),
+                    None => None,
+                },
+            },
+            name => return Err(format!("Unknown state action '{}'", name)),
+        };
+        let state_path = match (value("statefile"), value("config")) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, Some(path)) => Config::load(path)?
+                .statefile
+                .map_or_else(default_state_path, PathBuf::from),
+            (None, None) => default_state_path(),
+        };
+        let files = action_args
+            .values_of_lossy("file")
+            .or_else(|| args.values_of_lossy("file"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
             .collect();
+        Ok(StateArgs {
+            action,
+            state_path,
+            check: value("check").map(String::from),
+            files,
+        })
+    }
+
+    /// Tests if the action applies to a state.
+    /// # Arguments
+    /// * `state` - The state of a log file
+    pub fn applies_to(&self, state: &State) -> bool {
+        self.check.as_ref().map_or(true, |check| state.check.as_ref() == Some(check))
+            && (self.files.is_empty() || self.files.contains(&state.path))
+    }
+}
+
+impl Config {
+    /// Load a configuration file.
+    /// # Arguments
+    /// * `path` - The path to the configuration file
+    fn load(path: &str) -> Result<Self, String> {
+        let content = read_to_string(path)
+            .map_err(|e| format!("Could not read configuration file '{}': {}", path, e))?;
+        toml::from_str(&content)
+            .map_err(|e| format!("Could not parse configuration file '{}': {}", path, e))
+    }
+
+    /// Get the options of a check extended by the command line options.
+    /// # Arguments
+    /// * `name` - The name of the check
+    /// * `options` - The command line options
+    fn check_options(mut self, name: &str, options: Options) -> Option<Options> {
+        let mut check_options = self.checks.remove(name)?;
+        check_options.merge(options);
+        if check_options.statefile.is_none() {
+            check_options.statefile = self.statefile;
+        }
+        Some(check_options)
+    }
+}
+
+impl Options {
+    /// Get the options from the command line arguments.
+    /// # Arguments
+    /// * `args` - The parsed command line arguments
+    fn from_matches(args: &ArgMatches) -> Self {
+        let values = |name: &str| args.values_of_lossy(name).unwrap_or_default();
+        let value = |name: &str| args.value_of(name).map(String::from);
+        Options {
+            file: values("file"),
+            line: value("linepattern"),
+            linepreset: value("linepreset"),
+            end: value("endpattern"),
+            warningpattern: values("warningpattern"),
+            criticalpattern: values("criticalpattern"),
+            warningpatternfile: values("warningpatternfile"),
+            criticalpatternfile: values("criticalpatternfile"),
+            unknownpattern: values("unknownpattern"),
+            countpattern: values("countpattern"),
+            ratepattern: values("ratepattern"),
+            infopattern: values("infopattern"),
+            valuepattern: values("valuepattern"),
+            valuestat: values("valuestat"),
+            valuewindow: value("valuewindow"),
+            excludepattern: values("excludepattern"),
+            okpattern: values("okpattern"),
+            expectpattern: values("expectpattern"),
+            expectstatus: value("expectstatus"),
+            maxage: values("maxage"),
+            correlate: values("correlate")
+                .chunks(3)
+                .map(|correlate| correlate.to_vec())
+                .collect(),
+            correlatestatus: value("correlatestatus"),
+            warningexcludepattern: values("warningexcludepattern"),
+            criticalexcludepattern: values("criticalexcludepattern"),
+            matchpolicy: value("matchpolicy"),
+            output: value("output"),
+            maintenance: values("maintenance"),
+            learn: value("learn"),
+            statefile: value("statefile"),
+            keepstatus: value("keepstatus"),
+            namedpatterns: args.is_present("namedpatterns"),
+            group: vec![],
+            indices: pattern_indices(args),
+        }
+    }
+
+    /// Extend the options by other options. Single values of the other options replace the own values.
+    /// # Arguments
+    /// * `other` - The options to be merged into these options
+    fn merge(&mut self, mut other: Options) {
+        self.file.append(&mut other.file);
+        self.warningpattern.append(&mut other.warningpattern);
+        self.criticalpattern.append(&mut other.criticalpattern);
+        self.warningpatternfile.append(&mut other.warningpatternfile);
+        self.criticalpatternfile.append(&mut other.criticalpatternfile);
+        self.unknownpattern.append(&mut other.unknownpattern);
+        self.countpattern.append(&mut other.countpattern);
+        self.ratepattern.append(&mut other.ratepattern);
+        self.infopattern.append(&mut other.infopattern);
+        self.valuepattern.append(&mut other.valuepattern);
+        self.valuestat.append(&mut other.valuestat);
+        self.valuewindow = other.valuewindow.or(self.valuewindow.take());
+        self.excludepattern.append(&mut other.excludepattern);
+        self.okpattern.append(&mut other.okpattern);
+        self.expectpattern.append(&mut other.expectpattern);
+        self.expectstatus = other.expectstatus.or(self.expectstatus.take());
+        self.maxage.append(&mut other.maxage);
+        self.correlate.append(&mut other.correlate);
+        self.correlatestatus = other.correlatestatus.or(self.correlatestatus.take());
+        self.warningexcludepattern.append(&mut other.warningexcludepattern);
+        self.criticalexcludepattern.append(&mut other.criticalexcludepattern);
+        if other.line.is_some() || other.linepreset.is_some() {
+            self.line = other.line;
+            self.linepreset = other.linepreset;
+        }
+        self.end = other.end.or(self.end.take());
+        self.matchpolicy = other.matchpolicy.or(self.matchpolicy.take());
+        self.output = other.output.or(self.output.take());
+        self.maintenance.append(&mut other.maintenance);
+        self.learn = other.learn.or(self.learn.take());
+        self.statefile = other.statefile.or(self.statefile.take());
+        self.keepstatus = other.keepstatus.or(self.keepstatus.take());
+        self.namedpatterns |= other.namedpatterns;
+        // Groups of the same log files are merged, other groups are added
+        for group in other.group {
+            match self.group.iter_mut().find(|own| own.file == group.file) {
+                Some(own) => own.merge(group),
+                None => self.group.push(group),
+            }
+        }
+        if !other.indices.is_empty() {
+            self.indices = other.indices;
+        }
+    }
+}
+
+impl GroupOptions {
+    /// Get the options of a file group from its command line arguments starting with '--group'.
+    /// # Arguments
+    /// * `args` - The command line arguments of the group
+    fn from_args(args: Vec<String>) -> Self {
+        let preset_names: Vec<&str> = LINE_PRESETS.iter().map(|preset| preset.0).collect();
+        let args = clap_app!(group => (name: "--group")
+            (about: "Options of a group of log files. The problem patterns of the group are searched in addition to the problem patterns given before the first group. Exclude, OK, count, rate, info, value, expect and correlation patterns are given before the first group and apply to all groups.")
+            (@setting DisableVersion)
+            (@arg file: -f --file +takes_value +multiple +required "Log file to analyze. Append '#<rotatenamepattern>' to specify rotated files.")
+            (@arg linepattern: -l --line +takes_value "Pattern to detect new lines (default: the line pattern of the check)")
+            (@arg linepreset: --linepreset alias("line-preset") +takes_value possible_values(&preset_names) conflicts_with[linepattern] "Built-in pattern to detect new lines of a common log format")
+            (@arg endpattern: --end +takes_value "Pattern to detect the last line of a message (default: the end pattern of the check)")
+            (@arg warningpattern: -w --warningpattern +takes_value +multiple "Regex pattern to trigger a WARNING problem. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg criticalpattern: -c --criticalpattern +takes_value +multiple "Regex pattern to trigger a CRITICAL problem. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+            (@arg warningpatternfile: --warningpatternfile +takes_value +multiple "File with regex patterns to trigger a WARNING problem, one per line")
+            (@arg criticalpatternfile: --criticalpatternfile +takes_value +multiple "File with regex patterns to trigger a CRITICAL problem, one per line")
+            (@arg unknownpattern: --unknownpattern +takes_value +multiple "Regex pattern to trigger an UNKNOWN problem for messages making the monitoring unreliable. With --namedpatterns prepend 'NAME=' or 'NAME/FLAGS=' to name the pattern or set flags [imsxlfe].")
+        ).get_matches_from(args);
+        let values = |name: &str| args.values_of_lossy(name).unwrap_or_default();
+        GroupOptions {
+            file: values("file"),
+            line: args.value_of("linepattern").map(String::from),
+            linepreset: args.value_of("linepreset").map(String::from),
+            end: args.value_of("endpattern").map(String::from),
+            warningpattern: values("warningpattern"),
+            criticalpattern: values("criticalpattern"),
+            warningpatternfile: values("warningpatternfile"),
+            criticalpatternfile: values("criticalpatternfile"),
+            unknownpattern: values("unknownpattern"),
+            indices: pattern_indices(&args),
+        }
+    }
+
+    /// Extend the options by the options of a group of the same log files. Single values of the other
+    /// options replace the own values.
+    /// # Arguments
+    /// * `other` - The options to be merged into these options
+    fn merge(&mut self, mut other: GroupOptions) {
+        self.warningpattern.append(&mut other.warningpattern);
+        self.criticalpattern.append(&mut other.criticalpattern);
+        self.warningpatternfile.append(&mut other.warningpatternfile);
+        self.criticalpatternfile.append(&mut other.criticalpatternfile);
+        self.unknownpattern.append(&mut other.unknownpattern);
+        if other.line.is_some() || other.linepreset.is_some() {
+            self.line = other.line;
+            self.linepreset = other.linepreset;
+        }
+        self.end = other.end.or(self.end.take());
+        if !other.indices.is_empty() {
+            self.indices = other.indices;
+        }
+    }
+
+    /// Get the line pattern given directly or by a preset.
+    fn line_pattern(&self) -> Result<Option<&str>, String> {
+        match (self.line.as_deref(), self.linepreset.as_deref()) {
+            (Some(_), Some(_)) => Err(String::from("Use either a line pattern or a line preset.")),
+            (Some(line), None) => Ok(Some(line)),
+            (None, Some(name)) => preset::line_pattern(name)
+                .map(Some)
+                .ok_or(format!("Unknown line preset '{}'", name)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Get the continuation pattern of the line preset.
+    fn continuation_pattern(&self) -> Option<&'static str> {
+        self.linepreset.as_deref().and_then(preset::continuation_pattern)
+    }
+
+    /// Get the log file sets of the group including rotated log files.
+    fn files(&self) -> Result<Vec<Files>, String> {
         let mut all_files: Vec<Files> = vec![];
-        for file_arg in files_arg {
+        for file_arg in &self.file {
             // Split file argument to get the path and a pattern for rotated file names
             let file_parts: Vec<&str> = file_arg.splitn(2, '#').collect();
             let path = PathBuf::from(file_parts[0]);
@@ -81,7 +980,7 @@ impl Args {
                             .map_err(|e| format!("Could not get directory entry: {}", e))?
                             .file_name()
                             .into_string()
-                            .map_err(|_| format!("Could not convert directory entry filename."))?;
+                            .map_err(|_| "Could not convert directory entry filename.".to_string())?;
                         if pattern.is_match(&filename) {
                             let path = parent_dir.join(filename);
                             let file_time = file_modified(path.as_path())?;
@@ -99,78 +998,625 @@ impl Args {
             all_files.push(files);
         }
 
-        // linepattern
-        let linepattern = args.value_of("linepattern").unwrap_or("");
-        let line_re =
-            Regex::new(linepattern).map_err(|e| format!("Invalid line pattern: {}", e))?;
+        Ok(all_files)
+    }
 
-        // warningpattern
-        let mut patterns: Vec<Pattern> = vec![];
+    /// Get the patterns raising problems of the group.
+    /// Patterns keep their order on the command line across all pattern options to let the first
+    /// matching pattern classify a message. Patterns from a configuration file come first.
+    /// # Arguments
+    /// * `named` - Parse names and flags of the patterns
+    fn patterns(&self, named: bool) -> Result<Vec<Pattern>, String> {
+        let mut patterns: Vec<(usize, Pattern)> = vec![];
+        let pattern_args = [
+            ("warningpattern", &self.warningpattern, ProblemType::WARNING, false),
+            ("warningpatternfile", &self.warningpatternfile, ProblemType::WARNING, true),
+            ("criticalpattern", &self.criticalpattern, ProblemType::CRITICAL, false),
+            ("criticalpatternfile", &self.criticalpatternfile, ProblemType::CRITICAL, true),
+            ("unknownpattern", &self.unknownpattern, ProblemType::UNKNOWN, false),
+        ];
+        for (name, values, problem_type, is_file) in pattern_args.iter() {
+            for (position, value) in positions(&self.indices, name, values) {
+                if *is_file {
+                    for pattern in read_pattern_file(value, *problem_type, named)? {
+                        patterns.push((position, pattern));
+                    }
+                    continue;
+                }
+                match parse_pattern(value, *problem_type, named) {
+                    Ok(pattern) => patterns.push((position, pattern)),
+                    Err(e) => {
+                        return Err(format!(
+                            "Invalid {} pattern: {}",
+                            problem_type.to_string().to_lowercase(),
+                            e
+                        ))
+                    }
+                };
+            }
+        }
+        patterns.sort_by_key(|pattern| pattern.0);
+        Ok(patterns.into_iter().map(|pattern| pattern.1).collect())
+    }
+}
 
-        let warningpatterns = args.values_of_lossy("warningpattern").unwrap_or(vec![]);
-        for pattern in warningpatterns {
-            match Regex::new(&pattern) {
-                Ok(re) => patterns.push((ProblemType::WARNING, re)),
-                Err(e) => return Err(format!("Invalid warning pattern: {}", e)),
-            };
+/// Split the command line arguments into the arguments of the check and the arguments of each file
+/// group starting with '--group'. A '--group' given as value of an option starts no group.
+/// # Arguments
+/// * `argv` - The command line arguments
+fn split_group_args(argv: Vec<String>) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut check_args: Vec<String> = vec![];
+    let mut group_args: Vec<Vec<String>> = vec![];
+    let mut is_value = false;
+    for arg in argv {
+        let is_group = arg == "--group" && !is_value;
+        // Options like '-c' or '--line' take the next argument as value, '-cERROR' or '--line=^\[' do not
+        let is_option = (arg.len() == 2 && arg.starts_with('-')) || (arg.starts_with("--") && !arg.contains('='));
+        is_value = is_option && !FLAG_OPTIONS.contains(&arg.as_str());
+        if is_group {
+            group_args.push(vec![arg]);
+        } else if let Some(args) = group_args.last_mut() {
+            args.push(arg);
+        } else {
+            check_args.push(arg);
         }
+    }
+    (check_args, group_args)
+}
 
-        // criticalpattern
-        let criticalpatterns: Vec<_> = args.values_of_lossy("criticalpattern").unwrap_or(vec![]);
-        for pattern in criticalpatterns {
-            match Regex::new(&pattern) {
-                Ok(re) => patterns.push((ProblemType::CRITICAL, re)),
-                Err(e) => return Err(format!("Invalid critical pattern: {}", e)),
-            };
+/// Pair the values of an option with their positions on the command line.
+/// Values from a configuration file precede the command line values and get position 0.
+/// # Arguments
+/// * `indices` - The positions of the command line values by option name
+/// * `name` - The option name
+/// * `values` - The values of the option
+fn positions<'v>(
+    indices: &HashMap<String, Vec<usize>>,
+    name: &str,
+    values: &'v [String],
+) -> Vec<(usize, &'v String)> {
+    let indices = indices.get(name).map_or(&[][..], |indices| indices.as_slice());
+    let offset = values.len().saturating_sub(indices.len());
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match index.checked_sub(offset) {
+            Some(index) => (indices[index], value),
+            None => (0, value),
+        })
+        .collect()
+}
+
+/// Get the positions of the values of the pattern options on the command line.
+/// # Arguments
+/// * `args` - The parsed command line arguments
+fn pattern_indices(args: &ArgMatches) -> HashMap<String, Vec<usize>> {
+    PATTERN_OPTIONS
+        .iter()
+        .filter_map(|name| Some((name.to_string(), args.indices_of(name)?.collect())))
+        .collect()
+}
+
+/// Compile the pattern to determine a message start.
+/// Without line pattern every line starts a message unless an end pattern determines the message ends.
+/// # Arguments
+/// * `linepattern` - The regular expression
+/// * `endpattern` - The regular expression to determine the last line of a message
+fn parse_line(linepattern: Option<&str>, endpattern: Option<&str>) -> Result<Regex, String> {
+    let linepattern = match (linepattern, endpattern) {
+        (Some(linepattern), _) => linepattern,
+        // Matches no line
+        (None, Some(_)) => r"\z.",
+        (None, None) => "",
+    };
+    Regex::new(linepattern).map_err(|e| format!("Invalid line pattern: {}", e))
+}
+
+/// Compile the pattern to determine the last line of a message.
+/// # Arguments
+/// * `endpattern` - The regular expression
+fn parse_end(endpattern: Option<&str>) -> Result<Option<Regex>, String> {
+    match endpattern {
+        Some(endpattern) => Regex::new(endpattern)
+            .map(Some)
+            .map_err(|e| format!("Invalid end pattern: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Compile the pattern to determine the lines continuing a message.
+/// # Arguments
+/// * `continuationpattern` - The regular expression
+fn parse_continuation(continuationpattern: Option<&str>) -> Result<Option<Regex>, String> {
+    match continuationpattern {
+        Some(continuationpattern) => Regex::new(continuationpattern)
+            .map(Some)
+            .map_err(|e| format!("Invalid continuation pattern: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Get the default path to the state file in the user data directory or the temporary directory.
+fn default_state_path() -> PathBuf {
+    let mut statepath = match ProjectDirs::from("de", "osor", env!("CARGO_PKG_NAME")) {
+        Some(proj) => proj.data_dir().to_path_buf(),
+        None => temp_dir(),
+    };
+    statepath.push(format!("{}_state.json", env!("CARGO_PKG_NAME")));
+    statepath
+}
+
+/// Parse a duration given as 'NUMBER' or 'NUMBER[smhd]' to seconds.
+/// # Arguments
+/// * `value` - The duration string
+fn parse_duration(value: &str) -> Result<i64, String> {
+    let errstr = "Use 'NUMBER' or 'NUMBER[smhd]'.";
+    let re = Regex::new("^([0-9]+)([smhd]?)$")
+        .map_err(|e| format!("Could not validate value as duration: {}", e))?;
+    let caps = re.captures(value).ok_or(errstr)?;
+    let raw = caps.get(1).ok_or(errstr)?.as_str();
+    let unit = caps.get(2).ok_or(errstr)?.as_str();
+    let seconds: i64 = raw.parse().map_err(|_| errstr)?;
+    match unit {
+        "" | "s" => Ok(seconds),
+        "m" => Ok(seconds * 60),
+        "h" => Ok(seconds * 60 * 60),
+        "d" => Ok(seconds * 60 * 60 * 24),
+        _ => Err(errstr.into()),
+    }
+}
+
+/// Parse a date given as RFC 3339 date or as duration 'NUMBER[smhd]' ago.
+/// # Arguments
+/// * `value` - The date string
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(date) => Ok(date.with_timezone(&Utc)),
+        Err(_) => parse_duration(value)
+            .map(|seconds| Utc::now() - Duration::seconds(seconds))
+            .map_err(|_| format!("Invalid date '{}'. Use an RFC 3339 date or a duration.", value)),
+    }
+}
+
+/// Parse a limit given as count of messages 'NUMBER', as duration 'NUMBER[smhd]' with unit or as
+/// both 'NUMBER/NUMBER[smhd]'.
+/// # Arguments
+/// * `value` - The limit string
+fn parse_limit(value: &str) -> Result<(Option<usize>, Option<i64>), String> {
+    let errstr = "Use 'MESSAGES', 'DURATION' or 'MESSAGES/DURATION'.";
+    let mut messages: Option<usize> = None;
+    let mut window: Option<i64> = None;
+    for part in value.split('/') {
+        match part.parse::<usize>() {
+            Ok(count) if messages.is_none() && window.is_none() => messages = Some(count),
+            Err(_) if window.is_none() => window = Some(parse_duration(part)?),
+            _ => return Err(errstr.into()),
         }
+    }
+    Ok((messages, window))
+}
 
-        // statefile
-        let statepath = match args.value_of("statefile") {
-            Some(value) => PathBuf::from(value),
-            None => {
-                let statefilepath = |mut statepath: PathBuf| {
-                    statepath.push(format!("{}_state.json", env!("CARGO_PKG_NAME")));
-                    statepath
-                };
-                match ProjectDirs::from("de", "osor", env!("CARGO_PKG_NAME")) {
-                    Some(proj) => statefilepath(proj.data_dir().to_path_buf()),
-                    None => statefilepath(temp_dir()),
-                }
+/// Parse a pattern as regular expression or with its name and flags if named patterns are enabled.
+/// # Arguments
+/// * `value` - The pattern string
+/// * `problem_type` - Type of problem a matching message raises
+/// * `named` - Parse the name and flags of the pattern
+fn parse_pattern(value: &str, problem_type: ProblemType, named: bool) -> Result<Pattern, String> {
+    match named {
+        true => Pattern::parse(value, problem_type),
+        false => Pattern::new(value, problem_type),
+    }
+}
+
+/// Read patterns from a file containing one pattern per line.
+/// Empty lines and lines starting with '#' are skipped. Surrounding whitespace is removed.
+/// # Arguments
+/// * `path` - The path to the pattern file
+/// * `problem_type` - Type of problem a matching message raises
+/// * `named` - Parse names and flags of the patterns
+fn read_pattern_file(path: &str, problem_type: ProblemType, named: bool) -> Result<Vec<Pattern>, String> {
+    let content = read_to_string(path)
+        .map_err(|e| format!("Could not read pattern file '{}': {}", path, e))?;
+    let mut patterns: Vec<Pattern> = vec![];
+    // Whitespace is part of the patterns, lines() strips the line endings only
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_pattern(line, problem_type, named) {
+            Ok(pattern) => patterns.push(pattern),
+            Err(e) => {
+                return Err(format!(
+                    "Invalid pattern in pattern file '{}' line {}: {}",
+                    path,
+                    index + 1,
+                    e
+                ))
             }
         };
+    }
+    Ok(patterns)
+}
 
-        // keepstatus
-        let keepstatus_errstr =
-            "Value for keepstatus has invalid format. Use 'NUMBER' or 'NUMBER[smhd]'.";
-        let keepstatus: i64 = match args.value_of("keepstatus") {
-            Some(value) => {
-                let re = Regex::new("^([0-9]+)([smhd]?)$")
-                    .map_err(|e| format!("Could not validate value as duration: {}", e))?;
-                match re.captures(value) {
-                    Some(caps) => {
-                        let raw = caps.get(1).ok_or(keepstatus_errstr)?.as_str();
-                        let unit = caps.get(2).ok_or(keepstatus_errstr)?.as_str();
-                        let seconds: i64 = raw.parse().unwrap();
-                        match unit {
-                            "" | "s" => seconds,
-                            "m" => seconds * 60,
-                            "h" => seconds * 60 * 60,
-                            "d" => seconds * 60 * 60 * 24,
-                            _ => return Err(keepstatus_errstr.into()),
-                        }
-                    }
-                    None => return Err(keepstatus_errstr.into()),
-                }
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::logfile::{find, Match};
+    use clap::ErrorKind;
+    use std::fs::write;
+
+    /// Write a temporary log file and get its path.
+    fn log_file(name: &str, content: &str) -> String {
+        let path = temp_dir().join(format!("check_logmultiline_{}.log", name));
+        write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    /// Get the options of a check from command line arguments.
+    fn options(args: &[&str]) -> Options {
+        let preset_names: Vec<&str> = LINE_PRESETS.iter().map(|preset| preset.0).collect();
+        let argv = [env!("CARGO_PKG_NAME")].iter().chain(args.iter());
+        Options::from_matches(&Command::app(&preset_names).get_matches_from(argv))
+    }
+
+    /// Search the log file of a check given by command line arguments from its start.
+    fn search(args: &[&str]) -> (Args, Match) {
+        let args = Args::from_options(options(args), None).unwrap();
+        let (files, group, rules) = args.file_sets().next().unwrap();
+        let state = State::new(files[0].clone(), None);
+        let matchh = find(files, &state, group, rules).unwrap();
+        (args, matchh)
+    }
+
+    #[test]
+    fn test_pattern_order() {
+        // given
+        let path = log_file("pattern_order", "Exception: timeout\n");
+        let arguments = [
+            (["-c", "Exception", "-w", "timeout"], ProblemType::CRITICAL),
+            (["-w", "timeout", "-c", "Exception"], ProblemType::WARNING),
+        ];
+
+        for (patterns, problem_type) in arguments.iter() {
+            let mut argv = vec!["-f", &path, "--matchpolicy", "first"];
+            argv.extend(patterns);
+
+            // when
+            let (_, matchh) = search(&argv);
+
+            // then
+            assert_eq!(matchh.messages.len(), 1);
+            assert_eq!(matchh.messages[0].message_type, *problem_type);
+        }
+    }
+
+    #[test]
+    fn test_named_patterns() {
+        // given
+        let path = log_file("named_patterns", "status=404\n");
+
+        for (named, name, label) in [(false, None, "status=500"), (true, Some("status"), "status")] {
+            let mut argv = vec!["-f", &path, "-c", "status=500"];
+            if named {
+                argv.push("--namedpatterns");
             }
-            None => 0,
+
+            // when
+            let args = Args::from_options(options(&argv), None).unwrap();
+
+            // then
+            assert_eq!(args.rules.patterns.patterns[0].name.as_deref(), name);
+            assert_eq!(args.rules.patterns.patterns[0].label(), label);
+        }
+    }
+
+    #[test]
+    fn test_pattern_file() {
+        // given
+        let path = temp_dir().join("check_logmultiline_patterns.txt");
+        write(&path, "# Java errors\r\n\r\nERROR \r\n  at com\\.example\r\n").unwrap();
+
+        // when
+        let patterns = read_pattern_file(&path.to_string_lossy(), ProblemType::CRITICAL, false).unwrap();
+
+        // then
+        let labels: Vec<&str> = patterns.iter().map(|pattern| pattern.label()).collect();
+        assert_eq!(labels, vec!["ERROR ", r"  at com\.example"]);
+        assert!(!patterns[0].re.is_match("ERRORS"));
+    }
+
+    #[test]
+    fn test_info_pattern() {
+        // given
+        let path = log_file("info_pattern", "User a logged in\nUser b logged in: ERROR\nUser c logged in\n");
+        let argv = ["-f", &path, "--namedpatterns", "--infopattern", r"logins=User \w+ logged in", "-w", "ERROR"];
+
+        // when
+        let (args, matchh) = search(&argv);
+
+        // then
+        assert_eq!(matchh.messages.len(), 1);
+        assert_eq!(matchh.messages[0].message_type, ProblemType::WARNING);
+        assert!(matchh.messages[0].patterns.is_empty());
+        assert_eq!(matchh.counts, vec![3]);
+        let info_pattern = &args.rules.patterns.count_patterns[0];
+        assert_eq!(info_pattern.2.label(), "logins");
+        assert_eq!(info_pattern.0.check(3.0), ProblemType::OK);
+        assert_eq!(info_pattern.0.perfdata(), "");
+    }
+
+    #[test]
+    fn test_end_pattern() {
+        // given
+        let path = log_file("end_pattern", "BEGIN job 1\nERROR failed\nEND\nBEGIN job 2\nEND\n");
+        let argv = ["-f", &path, "--end", "^END", "-c", "ERROR"];
+
+        // when
+        let (_, matchh) = search(&argv);
+
+        // then
+        assert_eq!(matchh.messages.len(), 1);
+        assert_eq!(matchh.messages[0].message, "BEGIN job 1\nERROR failed\nEND\n");
+        assert_eq!(matchh.messages[0].line_number, 1);
+    }
+
+    #[test]
+    fn test_continuation() {
+        // given
+        let text = "May  1 12:00:01 host app[123]: Failed\n    continued text\nFailed without header\n";
+        let path = log_file("continuation", text);
+        let argv = ["-f", &path, "--linepreset", "syslog", "-c", "Failed"];
+
+        // when
+        let (_, matchh) = search(&argv);
+
+        // then
+        let messages: Vec<&str> = matchh.messages.iter().map(|message| message.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["May  1 12:00:01 host app[123]: Failed\n    continued text\n", "Failed without header\n"]
+        );
+    }
+
+    #[test]
+    fn test_expect_pattern() {
+        // given
+        let path = log_file("expect_pattern", "");
+        let argv = ["-f", &path, "--expectpattern", "20m,run finished", "--expectpattern", "1h,a{1,3}"];
+
+        // when
+        let args = Args::from_options(options(&argv), None).unwrap();
+
+        // then
+        let expect_patterns = &args.rules.patterns.expect_patterns;
+        assert_eq!(expect_patterns[0].0, 1200);
+        assert_eq!(expect_patterns[0].1.label(), "run finished");
+        assert_eq!(expect_patterns[1].0, 3600);
+        assert_eq!(expect_patterns[1].1.label(), "a{1,3}");
+        assert!(Args::from_options(options(&["-f", &path, "--expectpattern", "run finished"]), None).is_err());
+    }
+
+    #[test]
+    fn test_maintenance_target() {
+        // given
+        let path = log_file("maintenance_target", "");
+        let args = |window: &str| {
+            let argv = ["-f", &path, "--namedpatterns", "-c", "oom=OutOfMemoryError", "--maintenance", window];
+            Args::from_options(options(&argv), None)
         };
 
-        Ok(Args {
-            files: all_files,
-            line_re: line_re,
-            patterns: patterns,
-            state_path: PathBuf::from(statepath),
-            keep_status: keepstatus,
-        })
+        let windows = [
+            String::from("22:00-02:00,pattern:oom"),
+            format!("22:00-02:00,file:{}", path),
+            String::from("22:00-02:00,pattern:OutOfMemoryError"),
+            String::from("22:00-02:00,file:other.log"),
+        ];
+
+        // when
+        let results: Vec<bool> = windows.iter().map(|window| args(window).is_ok()).collect();
+
+        // then
+        assert_eq!(results, vec![true, true, false, false]);
+    }
+
+    #[test]
+    fn test_config() {
+        // given
+        let path = log_file("config", "");
+        let config: Config = toml::from_str(&format!(
+            r#"
+            statefile = "/var/lib/check_logmultiline/state.json"
+
+            [checks.app]
+            file = ['{}']
+            line = '^\['
+            end = '^END'
+            criticalpattern = ['OutOfMemoryError']
+            keepstatus = "1d"
+            "#,
+            path
+        ))
+        .unwrap();
+        let argv = ["--config", "app.toml", "--check", "app", "-c", "Exception", "-k", "2h", "--linepreset", "iso8601"];
+
+        // when
+        let options = config.check_options("app", options(&argv)).unwrap();
+        let args = Args::from_options(options, Some(String::from("app"))).unwrap();
+
+        // then
+        let labels: Vec<&str> = args.rules.patterns.patterns.iter().map(|pattern| pattern.label()).collect();
+        assert_eq!(labels, vec!["OutOfMemoryError", "Exception"]);
+        assert_eq!(args.keep_status, 7200);
+        assert_eq!(args.groups[0].line_re.as_str(), preset::line_pattern("iso8601").unwrap());
+        assert_eq!(args.groups[0].end_re.as_ref().map(Regex::as_str), Some("^END"));
+        assert_eq!(args.state_path, PathBuf::from("/var/lib/check_logmultiline/state.json"));
+    }
+
+    #[test]
+    fn test_group_args() {
+        // given
+        let argv: Vec<String> = [
+            "check_logmultiline",
+            "-c",
+            "OutOfMemoryError",
+            "-w",
+            "--group",
+            "--group",
+            "-f",
+            "app.log",
+            "-cERROR",
+            "--group",
+            "-f",
+            "web.log",
+            "--linepreset",
+            "nginx-error",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        // when
+        let (check_args, group_args) = split_group_args(argv);
+        let groups: Vec<GroupOptions> = group_args.into_iter().map(GroupOptions::from_args).collect();
+
+        // then
+        assert_eq!(check_args, vec!["check_logmultiline", "-c", "OutOfMemoryError", "-w", "--group"]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].file, vec!["app.log"]);
+        assert_eq!(groups[0].criticalpattern, vec!["ERROR"]);
+        assert_eq!(groups[0].line_pattern(), Ok(None));
+        assert_eq!(groups[1].file, vec!["web.log"]);
+        assert_eq!(groups[1].line_pattern(), Ok(preset::line_pattern("nginx-error")));
+    }
+
+    #[test]
+    fn test_file_sets() {
+        // given
+        let app_path = log_file("file_sets_app", "");
+        let web_path = log_file("file_sets_web", "");
+        let mut options = options(&["-c", "OutOfMemoryError", "-l", "^\\[", "--group"]);
+        let group = |args: &[&str]| GroupOptions::from_args(args.iter().map(|arg| arg.to_string()).collect());
+        options.group = vec![
+            group(&["--group", "-f", &app_path, "-cERROR"]),
+            group(&["--group", "-f", &web_path]),
+        ];
+
+        // when
+        let args = Args::from_options(options, None).unwrap();
+
+        // then
+        let file_sets: Vec<(&Files, &FileGroup, &Rules)> = args.file_sets().collect();
+        assert_eq!(file_sets.len(), 2);
+        assert_eq!(file_sets[0].0, &vec![PathBuf::from(&app_path)]);
+        let labels: Vec<&str> = file_sets[0].2.patterns.patterns.iter().map(|pattern| pattern.label()).collect();
+        assert_eq!(labels, vec!["OutOfMemoryError", "ERROR"]);
+        assert_eq!(file_sets[1].0, &vec![PathBuf::from(&web_path)]);
+        assert!(std::ptr::eq(file_sets[1].2, &args.rules));
+        assert!(file_sets.iter().all(|file_set| file_set.1.line_re.as_str() == "^\\["));
+        assert!(args.contains_file(Path::new(&web_path)));
+    }
+
+    #[test]
+    fn test_config_groups() {
+        // given
+        let app_path = log_file("config_groups_app", "");
+        let web_path = log_file("config_groups_web", "");
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [checks.web]
+            criticalpattern = ['OutOfMemoryError']
+
+            [[checks.web.group]]
+            file = ['{}']
+            line = '^\['
+            criticalpattern = ['SQLException']
+
+            [[checks.web.group]]
+            file = ['{}']
+            linepreset = 'nginx-error'
+            "#,
+            app_path, web_path
+        ))
+        .unwrap();
+        let mut options = options(&["--config", "web.toml", "--check", "web", "--group"]);
+        let group_args = ["--group", "-f", &app_path, "-c", "ERROR", "--linepreset", "log4j"];
+        options.group = vec![GroupOptions::from_args(group_args.iter().map(|arg| arg.to_string()).collect())];
+
+        // when
+        let options = config.check_options("web", options).unwrap();
+        let args = Args::from_options(options, Some(String::from("web"))).unwrap();
+
+        // then
+        let file_sets: Vec<(&Files, &FileGroup, &Rules)> = args.file_sets().collect();
+        assert_eq!(file_sets.len(), 2);
+        let labels: Vec<&str> = file_sets[0].2.patterns.patterns.iter().map(|pattern| pattern.label()).collect();
+        assert_eq!(labels, vec!["OutOfMemoryError", "SQLException", "ERROR"]);
+        assert_eq!(file_sets[0].1.line_re.as_str(), preset::line_pattern("log4j").unwrap());
+        assert_eq!(file_sets[1].1.line_re.as_str(), preset::line_pattern("nginx-error").unwrap());
+        assert!(std::ptr::eq(file_sets[1].2, &args.rules));
+    }
+
+    #[test]
+    fn test_state_args() {
+        // given
+        let preset_names: Vec<&str> = LINE_PRESETS.iter().map(|preset| preset.0).collect();
+        let argv = [
+            "check_logmultiline",
+            "state",
+            "-s",
+            "state.json",
+            "forget",
+            "--fingerprint",
+            "47773f6b3f8a08f4",
+            "-f",
+            "app.log",
+        ];
+        let matches = Command::app(&preset_names).get_matches_from(argv.iter());
+
+        // when
+        let state_args = StateArgs::from_matches(matches.subcommand_matches("state").unwrap()).unwrap();
+
+        // then
+        assert!(matches!(
+            &state_args.action,
+            StateAction::Forget(fingerprints) if fingerprints == &["47773f6b3f8a08f4"]
+        ));
+        assert_eq!(state_args.state_path, PathBuf::from("state.json"));
+        assert_eq!(state_args.files, vec![PathBuf::from("app.log")]);
+        assert!(state_args.applies_to(&State::new(PathBuf::from("app.log"), Some(String::from("app")))));
+        assert!(!state_args.applies_to(&State::new(PathBuf::from("other.log"), None)));
+        let state_args = StateArgs {
+            check: Some(String::from("app")),
+            ..state_args
+        };
+        assert!(state_args.applies_to(&State::new(PathBuf::from("app.log"), Some(String::from("app")))));
+        assert!(!state_args.applies_to(&State::new(PathBuf::from("app.log"), Some(String::from("web")))));
+        assert!(!state_args.applies_to(&State::new(PathBuf::from("app.log"), None)));
+    }
+
+    #[test]
+    fn test_state_ack_args() {
+        // given
+        let preset_names: Vec<&str> = LINE_PRESETS.iter().map(|preset| preset.0).collect();
+        let state_args = |args: &[&str]| {
+            let argv = ["check_logmultiline", "state", "-s", "state.json", "ack"].iter().chain(args.iter());
+            let matches = Command::app(&preset_names).get_matches_from_safe(argv).map_err(|e| e.kind)?;
+            Ok::<_, ErrorKind>(StateArgs::from_matches(matches.subcommand_matches("state").unwrap()))
+        };
+
+        // when
+        let bare = state_args(&[]);
+        let conflicting = state_args(&["--all", "--before", "2h"]);
+        let invalid = state_args(&["--before", "2x"]).unwrap();
+        let all = state_args(&["--all"]).unwrap().unwrap();
+        let before = state_args(&["--before", "2020-05-01T12:00:00Z"]).unwrap().unwrap();
+
+        // then
+        assert_eq!(bare.err(), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(conflicting.err(), Some(ErrorKind::ArgumentConflict));
+        assert!(invalid.is_err());
+        assert!(matches!(all.action, StateAction::Ack { fingerprints, before: None } if fingerprints.is_empty()));
+        assert!(matches!(before.action, StateAction::Ack { before: Some(_), .. }));
     }
 }
@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Normalize messages to fingerprints identifying repeated messages.
+
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+/// Matches the variable parts of a message: UUIDs, timestamps, hex ids and numbers.
+fn variables_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?P<uuid>\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b)",
+            r"|(?P<ts>\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?)?",
+            r"|\d{2}:\d{2}:\d{2}(?:[.,]\d+)?)",
+            r"|(?P<hex>\b(?:0x)?[0-9a-fA-F]{6,}\b)",
+            r"|(?P<n>\d+)",
+        ))
+        .unwrap()
+    })
+}
+
+/// Mask UUIDs, timestamps, hex ids and numbers in a message.
+/// Hex ids need at least 6 digits and one decimal digit to not mask words like 'facade'.
+/// # Arguments
+/// * `text` - The message text
+pub fn normalize(text: &str) -> String {
+    variables_re()
+        .replace_all(text, |caps: &Captures| {
+            if caps.name("uuid").is_some() {
+                String::from("<uuid>")
+            } else if caps.name("ts").is_some() {
+                String::from("<ts>")
+            } else if let Some(hex) = caps.name("hex") {
+                let hex = hex.as_str();
+                if hex.chars().all(|c| c.is_ascii_digit()) {
+                    String::from("<n>")
+                } else if hex.starts_with("0x") || hex.chars().any(|c| c.is_ascii_digit()) {
+                    String::from("<hex>")
+                } else {
+                    hex.to_string()
+                }
+            } else {
+                String::from("<n>")
+            }
+        })
+        .into_owned()
+}
+
+/// Get the fingerprint of a message as hex string of the 64 bit FNV-1a hash of the normalized
+/// message. The hash is stable across versions to compare fingerprints saved in the state file.
+/// # Arguments
+/// * `text` - The message text
+pub fn fingerprint(text: &str) -> String {
+    let hash = normalize(text).bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("2020-05-01 12:00:01,123 Connection refused to 10.0.0.5:5432"),
+            "<ts> Connection refused to <n>.<n>.<n>.<n>:<n>"
+        );
+        assert_eq!(
+            normalize("request 123e4567-e89b-12d3-a456-426614174000 at 0x7f3a2c facade 1234567 a1b2c3d4"),
+            "request <uuid> at <hex> facade <n> <hex>"
+        );
+        assert_eq!(fingerprint("error 1"), fingerprint("error 2"));
+        assert_ne!(fingerprint("error 1"), fingerprint("failure 1"));
+    }
+}
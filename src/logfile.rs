@@ -4,19 +4,110 @@
 
 //! Analyze log files.
 
-use crate::args::Files;
+use crate::args::{FileGroup, Files};
+use crate::fingerprint::fingerprint;
+use crate::matcher::Matcher;
 use crate::state::State;
-use chrono::prelude::*;
-use regex::Regex;
+use crate::threshold::Thresholds;
+use chrono::{prelude::*, Duration};
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::{metadata, File};
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::time::SystemTime;
 
-/// A tuple containing the type of the pattern and the pattern.
-pub type Pattern = (ProblemType, Regex);
+/// A regular expression to search for with an optional name.
+#[derive(Clone)]
+pub struct Pattern {
+    /// Name of the pattern shown in output and performance data.
+    pub name: Option<String>,
+
+    /// Type of problem a matching message raises.
+    pub problem_type: ProblemType,
+
+    /// The pattern as given by the user without name and flags.
+    pub text: String,
+
+    /// The regular expression.
+    pub re: Regex,
+
+    /// The fixed string the pattern matches if it contains no regular expression syntax.
+    pub literal: Option<String>,
+
+    /// Match case insensitive.
+    pub case_insensitive: bool,
+
+    /// The part of a message the pattern is applied to.
+    pub scope: Scope,
+}
+
+/// The part of a message a pattern is applied to.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Scope {
+    /// The whole message.
+    Message,
+
+    /// The first line of the message.
+    FirstLine,
+
+    /// Each line of the message individually.
+    Lines,
+}
+
+/// A tuple containing the count thresholds of the pattern, the optional time window in seconds
+/// to count matches in and the pattern. Patterns counting for performance data only have no thresholds.
+pub type CountPattern = (Thresholds, Option<i64>, Pattern);
+
+/// A tuple containing the thresholds for the value captured by the pattern and the pattern.
+/// The value is captured by the group named 'value' or the first group.
+pub type ValuePattern = (Thresholds, Pattern);
+
+/// A tuple containing the seconds within which the pattern is expected to match and the pattern.
+pub type ExpectPattern = (i64, Pattern);
+
+/// A tuple containing the type of pattern the exclude pattern applies to and the pattern.
+/// An exclude pattern without type applies to all patterns.
+pub type ExcludePattern = (Option<ProblemType>, Regex);
+
+/// A rule raising a problem if a message matching the start pattern is not followed by a message
+/// matching the end pattern with the same key within a limit.
+/// The key is captured by the group named 'key' or the first group of both patterns.
+#[derive(Clone)]
+pub struct Correlation {
+    /// Pattern starting the correlation. Its problem type is raised if the end is missing.
+    pub start: Pattern,
+
+    /// Pattern ending the correlation.
+    pub end: Pattern,
+
+    /// The count of following messages the end has to be found in.
+    pub messages: Option<usize>,
+
+    /// The amount of seconds across runs the end has to be found in.
+    pub window: Option<i64>,
+}
+
+/// A started correlation waiting for its end.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingCorrelation {
+    /// Label of the start pattern of the correlation.
+    pub correlation: String,
+
+    /// The key captured by the start pattern.
+    pub key: String,
+
+    /// The count of messages following the start message.
+    pub messages: usize,
+
+    /// The date of the run the start message has been found in.
+    pub started: DateTime<Utc>,
+
+    /// The start message.
+    pub message: Message,
+}
 
 /// The struct contains the informations about matches in a log file.
 #[derive(Clone, Serialize, Deserialize)]
@@ -36,25 +127,99 @@ pub struct Match {
     /// Matching messages.
     pub messages: Vec<Message>,
 
+    /// The count of matching messages dropped by exclude patterns.
+    #[serde(default)]
+    pub excluded_count: usize,
+
+    /// The count of problem messages dropped during maintenance windows.
+    #[serde(default)]
+    pub suppressed_count: usize,
+
+    /// The count of messages dropped as learned in learning mode.
+    #[serde(default)]
+    pub known_count: usize,
+
+    /// The count of matching messages for each count pattern.
+    #[serde(skip)]
+    pub counts: Vec<usize>,
+
+    /// The captured values for each value pattern.
+    #[serde(skip)]
+    pub values: Vec<Vec<f64>>,
+
+    /// Recoveries found by OK patterns.
+    #[serde(skip)]
+    pub recoveries: Vec<Recovery>,
+
+    /// The count of matching messages for each expect pattern.
+    #[serde(skip)]
+    pub expected: Vec<usize>,
+
+    /// Correlations waiting for their end.
+    #[serde(skip)]
+    pub pending: Vec<PendingCorrelation>,
+
     /// The date til when the message should be kept if keep_status is active.
     pub keep_until: DateTime<Utc>,
+
+    /// The date of the run the messages have been found in. Unknown for states of previous versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked: Option<DateTime<Utc>>,
+
+    /// Indexes of the messages added by `push_message` by their repetition keys.
+    #[serde(skip)]
+    message_indexes: HashMap<RepetitionKey, usize>,
 }
 
+/// A tuple containing the fingerprint, the type and the key of a message. Messages with the same
+/// repetition key are grouped.
+type RepetitionKey = (String, ProblemType, Option<String>);
+
 /// A multiline message from a log file.
+/// Repeated messages with the same fingerprint are grouped into the first one.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Message {
     /// The line number the message started in.
     pub line_number: i64,
 
+    /// The line number the last repetition of the message started in.
+    #[serde(default)]
+    pub last_line_number: i64,
+
+    /// The count of repetitions of the message.
+    #[serde(default = "Message::single")]
+    pub count: usize,
+
+    /// Identifies repetitions of the message with different numbers, ids or timestamps.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub fingerprint: String,
+
     /// Type of pattern found.
     pub message_type: ProblemType,
 
     /// The message string.
     pub message: String,
+
+    /// The names of the named patterns matching the message.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
+    /// The value of the capture group named 'key' of the matching patterns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// The captured groups of the pattern determining the message type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captures: Option<String>,
+
+    /// The line matching the pattern determining the message type if the pattern is applied to each line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
 }
 
 /// The type of pattern or problem.
-#[derive(Debug, Clone, PartialEq, Copy, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy, Serialize, Deserialize)]
 pub enum ProblemType {
     OK = 0,
     WARNING = 1,
@@ -62,21 +227,301 @@ pub enum ProblemType {
     UNKNOWN = 3,
 }
 
+/// The policy to choose the type of a message matching several patterns.
+#[derive(Debug, Clone, PartialEq, Copy, Default)]
+pub enum MatchPolicy {
+    /// The pattern with the highest severity determines the message type.
+    #[default]
+    Highest,
+
+    /// The first matching pattern determines the message type.
+    First,
+}
+
+/// The part of a message printed in the output. Kept messages in the state file and the JSON output
+/// always contain whole messages.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum OutputMode {
+    /// The whole message.
+    Full,
+
+    /// The first line of the message or the line matching a pattern applied to each line.
+    FirstLine,
+
+    /// The capture groups of the pattern determining the message type or the line printed by
+    /// `FirstLine` if it has none.
+    Captures,
+
+    /// The whole message with its type, line numbers, patterns and captures as one JSON object per line.
+    Json,
+}
+
+/// All patterns to search for in messages before they are prepared for matching.
+/// Unset patterns default to none and the policy to `MatchPolicy::Highest`.
+#[derive(Clone, Default)]
+pub struct RulePatterns {
+    /// Patterns to raise problems for.
+    pub patterns: Vec<Pattern>,
+
+    /// Patterns to count matching messages for.
+    pub count_patterns: Vec<CountPattern>,
+
+    /// Patterns to classify messages by captured values.
+    pub value_patterns: Vec<ValuePattern>,
+
+    /// Patterns to drop otherwise matching messages.
+    pub excludes: Vec<ExcludePattern>,
+
+    /// Patterns to clear previous problems.
+    pub ok_patterns: Vec<Pattern>,
+
+    /// Patterns expected to match regularly. The problem type applies if they are missing.
+    pub expect_patterns: Vec<ExpectPattern>,
+
+    /// Rules for messages expected to follow other messages.
+    pub correlations: Vec<Correlation>,
+
+    /// The policy to choose the type of a message matching several patterns.
+    pub policy: MatchPolicy,
+}
+
+/// All patterns to search for in messages prepared for matching.
+pub struct Rules {
+    /// The patterns to search for.
+    pub patterns: RulePatterns,
+
+    /// Matcher for the problem, count, value, OK, expect and correlation patterns in this order.
+    matcher: Matcher,
+}
+
+/// A recovery found by an OK pattern clearing previous problems.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recovery {
+    /// Name of the problem patterns to clear. Problems of all patterns are cleared without name.
+    pub name: Option<String>,
+
+    /// The key captured by the problem patterns to clear. Problems with any key are cleared without key.
+    pub key: Option<String>,
+}
+
+impl Recovery {
+    /// Tests if a problem message is cleared by this recovery.
+    /// # Arguments
+    /// * `message` - The problem message
+    pub fn clears(&self, message: &Message) -> bool {
+        self.name
+            .as_ref()
+            .map_or(true, |name| message.patterns.contains(name))
+            && self
+                .key
+                .as_ref()
+                .map_or(true, |key| message.key.as_ref() == Some(key))
+    }
+}
+
+impl RulePatterns {
+    /// Get the names of the named problem, count, value, expect and correlation start patterns.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.patterns
+            .iter()
+            .chain(self.count_patterns.iter().map(|pattern| &pattern.2))
+            .chain(self.value_patterns.iter().map(|pattern| &pattern.1))
+            .chain(self.expect_patterns.iter().map(|pattern| &pattern.1))
+            .chain(self.correlations.iter().map(|correlation| &correlation.start))
+            .filter_map(|pattern| pattern.name.as_deref())
+    }
+}
+
+impl Rules {
+    /// Create the rules and prepare the patterns for matching.
+    /// # Arguments
+    /// * `patterns` - All patterns to search for in messages
+    pub fn new(patterns: RulePatterns) -> Result<Self, String> {
+        let all_patterns: Vec<&Pattern> = patterns
+            .patterns
+            .iter()
+            .chain(patterns.count_patterns.iter().map(|pattern| &pattern.2))
+            .chain(patterns.value_patterns.iter().map(|pattern| &pattern.1))
+            .chain(patterns.ok_patterns.iter())
+            .chain(patterns.expect_patterns.iter().map(|pattern| &pattern.1))
+            .chain(patterns.correlations.iter().flat_map(|correlation| vec![&correlation.start, &correlation.end]))
+            .collect();
+        let matcher = Matcher::new(&all_patterns)?;
+        Ok(Rules { patterns, matcher })
+    }
+
+    /// Create rules raising problems for other patterns and sharing all other patterns with these rules.
+    /// # Arguments
+    /// * `patterns` - Patterns to raise problems for
+    pub fn with_patterns(&self, patterns: Vec<Pattern>) -> Result<Self, String> {
+        Rules::new(RulePatterns {
+            patterns,
+            ..self.patterns.clone()
+        })
+    }
+}
+
+impl Pattern {
+    /// Get the part of a message the pattern is applied to: the whole message, its first line or
+    /// the first matching line.
+    /// # Arguments
+    /// * `text` - The message text
+    pub fn matching_text<'t>(&self, text: &'t str) -> Option<&'t str> {
+        match self.scope {
+            Scope::Message => Some(text),
+            Scope::FirstLine => text.lines().next(),
+            Scope::Lines => text.lines().find(|line| self.re.is_match(line)),
+        }
+    }
+
+    /// Get the capture groups of the first match in the part of a message the pattern is applied to.
+    /// # Arguments
+    /// * `text` - The message text
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        self.re.captures(self.matching_text(text)?)
+    }
+
+    /// Get the key captured by the group named 'key' or the first group.
+    /// # Arguments
+    /// * `text` - The text to capture the key from
+    pub fn capture_key(&self, text: &str) -> Option<String> {
+        let caps = self.captures(text)?;
+        caps.name("key")
+            .or_else(|| caps.get(1))
+            .map(|key| key.as_str().to_string())
+    }
+
+    /// Parse a pattern given as 'REGEX', 'NAME=REGEX' or 'NAME/FLAGS=REGEX'. The name is optional if
+    /// flags are given.
+    /// A leading '=' prevents a regular expression containing '=' to be taken as name.
+    ///
+    /// Flags:
+    /// * `i` - Case insensitive
+    /// * `m` - `^` and `$` match at line starts and ends
+    /// * `s` - `.` matches new lines
+    /// * `x` - Ignore whitespace and allow comments in the regular expression
+    /// * `l` - Match the pattern as fixed string instead of a regular expression
+    /// * `f` - Apply the pattern to the first line of a message only
+    /// * `e` - Apply the pattern to each line of a message individually
+    ///
+    /// Patterns without regular expression syntax are matched as fixed strings too.
+    /// # Arguments
+    /// * `value` - The pattern string
+    /// * `problem_type` - Type of problem a matching message raises
+    pub fn parse(value: &str, problem_type: ProblemType) -> Result<Self, String> {
+        let name_re = Regex::new(r"^([A-Za-z_][\w.-]*)?(?:/([a-z]+))?=")
+            .map_err(|e| format!("Could not validate pattern name: {}", e))?;
+        match name_re.captures(value) {
+            Some(caps) => Pattern::compile(
+                caps.get(1).map(|name| name.as_str().to_string()),
+                caps.get(2).map_or("", |flags| flags.as_str()),
+                &value[caps.get(0).unwrap().end()..],
+                problem_type,
+            ),
+            None => Pattern::compile(None, "", value, problem_type),
+        }
+    }
+
+    /// Create a pattern without name and flags from a regular expression.
+    /// Patterns without regular expression syntax are matched as fixed strings.
+    /// # Arguments
+    /// * `regex` - The regular expression
+    /// * `problem_type` - Type of problem a matching message raises
+    pub fn new(regex: &str, problem_type: ProblemType) -> Result<Self, String> {
+        Pattern::compile(None, "", regex, problem_type)
+    }
+
+    /// Compile a pattern with its name and flags.
+    /// # Arguments
+    /// * `name` - The optional name of the pattern
+    /// * `flags` - The flags of the pattern
+    /// * `pattern` - The regular expression
+    /// * `problem_type` - Type of problem a matching message raises
+    fn compile(
+        name: Option<String>,
+        flags: &str,
+        pattern: &str,
+        problem_type: ProblemType,
+    ) -> Result<Self, String> {
+        if let Some(flag) = flags.chars().find(|flag| !"imsxlfe".contains(*flag)) {
+            return Err(format!("Unknown pattern flag '{}'", flag));
+        }
+        let scope = match (flags.contains('f'), flags.contains('e')) {
+            (false, false) => Scope::Message,
+            (true, false) => Scope::FirstLine,
+            (false, true) => Scope::Lines,
+            (true, true) => return Err(String::from("Pattern flags 'f' and 'e' exclude each other")),
+        };
+        let case_insensitive = flags.contains('i');
+        let is_literal = flags.contains('l')
+            || (!flags.contains('x') && regex::escape(pattern) == pattern);
+
+        // Flags are set inline to keep them when the pattern is used in a regex set
+        let inline_flags: String = "imsx"
+            .chars()
+            .filter(|flag| flags.contains(*flag) && !(is_literal && *flag == 'x'))
+            .collect();
+        let source = match is_literal {
+            true => regex::escape(pattern),
+            false => pattern.to_string(),
+        };
+        let source = match inline_flags.is_empty() {
+            true => source,
+            false => format!("(?{}){}", inline_flags, source),
+        };
+        let re = Regex::new(&source).map_err(|e| e.to_string())?;
+
+        // Case insensitive fixed strings are only matched without regex for ASCII
+        let literal = match is_literal && (!case_insensitive || pattern.is_ascii()) {
+            true => Some(pattern.to_string()),
+            false => None,
+        };
+
+        Ok(Pattern {
+            name,
+            problem_type,
+            text: pattern.to_string(),
+            re,
+            literal,
+            case_insensitive,
+            scope,
+        })
+    }
+
+    /// Get the name of the pattern or the pattern as given by the user if the pattern has no name.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.text)
+    }
+}
+
 impl Display for Match {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.output(OutputMode::Full))
+    }
+}
+
+impl Match {
+    /// Format the file path and the messages for output.
+    /// # Arguments
+    /// * `mode` - The part of the messages to be printed
+    pub fn output(&self, mode: OutputMode) -> String {
         let mut output = String::new();
 
+        if mode == OutputMode::Json {
+            let file = serde_json::json!({ "file": self.path.to_string_lossy(), "messages": self.messages });
+            output.push_str(&format!("{}\n", file));
+            return output;
+        }
+
         output.push_str(&format!("File: {}\n", self.path.to_str().unwrap()));
 
         for message in &self.messages {
-            output.push_str(&format!("{}\n", message));
+            output.push_str(&format!("{}\n", message.output(mode)));
         }
 
-        write!(f, "{}", output)
+        output
     }
-}
 
-impl Match {
     /// Tests if any message is CRITICAL.
     pub fn any_critical(&self) -> bool {
         self.messages
@@ -91,30 +536,74 @@ impl Match {
             .any(|message| message.message_type == ProblemType::WARNING)
     }
 
-    /// Counts the CRITICAL messages.
+    /// Tests if any message is UNKNOWN.
+    pub fn any_unknown(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|message| message.message_type == ProblemType::UNKNOWN)
+    }
+
+    /// Counts the CRITICAL messages including repetitions.
     pub fn count_critical(&self) -> usize {
         self.messages
             .iter()
             .filter(|message| message.message_type == ProblemType::CRITICAL)
-            .count()
+            .map(|message| message.count)
+            .sum()
     }
 
-    /// Counts the WARNING messages.
+    /// Counts the WARNING messages including repetitions.
     pub fn count_warning(&self) -> usize {
         self.messages
             .iter()
             .filter(|message| message.message_type == ProblemType::WARNING)
-            .count()
+            .map(|message| message.count)
+            .sum()
+    }
+
+    /// Counts the UNKNOWN messages including repetitions.
+    pub fn count_unknown(&self) -> usize {
+        self.messages
+            .iter()
+            .filter(|message| message.message_type == ProblemType::UNKNOWN)
+            .map(|message| message.count)
+            .sum()
+    }
+
+    /// Add a problem message or count it as repetition of a message with the same fingerprint.
+    /// # Arguments
+    /// * `message` - The problem message
+    pub fn push_message(&mut self, message: Message) {
+        let repetition_key = match message.repetition_key() {
+            Some(repetition_key) => repetition_key,
+            None => return self.messages.push(message),
+        };
+        match self.message_indexes.get(&repetition_key) {
+            Some(index) => self.messages[*index].repeat(&message),
+            None => {
+                self.message_indexes.insert(repetition_key, self.messages.len());
+                self.messages.push(message);
+            }
+        }
+    }
+
+    /// Drop the problem messages cleared by a recovery.
+    /// # Arguments
+    /// * `recovery` - The recovery clearing problems
+    pub fn clear(&mut self, recovery: &Recovery) {
+        self.messages.retain(|message| !recovery.clears(message));
+        self.message_indexes = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, message)| message.repetition_key().map(|repetition_key| (repetition_key, index)))
+            .collect();
     }
 }
 
 impl Display for Message {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}({}): {}",
-            self.message_type, self.line_number, self.message
-        )
+        write!(f, "{}", self.output(OutputMode::Full))
     }
 }
 
@@ -123,8 +612,98 @@ impl Message {
     pub fn new() -> Self {
         Message {
             line_number: 0,
+            last_line_number: 0,
+            count: 1,
+            fingerprint: String::new(),
             message_type: ProblemType::UNKNOWN,
             message: String::new(),
+            patterns: vec![],
+            key: None,
+            captures: None,
+            line: None,
+        }
+    }
+
+    /// The count of repetitions of a single message.
+    fn single() -> usize {
+        1
+    }
+
+    /// Set the fingerprint and the line range of a single message.
+    pub fn identify(&mut self) {
+        self.fingerprint = fingerprint(&self.message);
+        self.last_line_number = self.line_number;
+        self.count = 1;
+    }
+
+    /// Tests if another message is a repetition of this message.
+    /// Messages with different keys are kept apart to be cleared by recoveries individually.
+    /// Messages without fingerprint from previous versions are never repeated.
+    /// # Arguments
+    /// * `other` - The other message
+    pub fn is_repeated_by(&self, other: &Message) -> bool {
+        !self.fingerprint.is_empty()
+            && self.fingerprint == other.fingerprint
+            && self.message_type == other.message_type
+            && self.key == other.key
+    }
+
+    /// Get the key grouping repetitions of the message. Messages without fingerprint have none.
+    fn repetition_key(&self) -> Option<RepetitionKey> {
+        match self.fingerprint.is_empty() {
+            true => None,
+            false => Some((self.fingerprint.clone(), self.message_type, self.key.clone())),
+        }
+    }
+
+    /// Count the repetitions of a later message.
+    /// # Arguments
+    /// * `later` - The repeated message
+    pub fn repeat(&mut self, later: &Message) {
+        self.count += later.count;
+        self.last_line_number = later.last_line_number;
+    }
+
+    /// Get the part of the message to be printed.
+    /// # Arguments
+    /// * `mode` - The part of the message to be printed
+    pub fn text(&self, mode: OutputMode) -> &str {
+        let line = || {
+            self.line
+                .as_deref()
+                .unwrap_or_else(|| self.message.lines().next().unwrap_or_default())
+        };
+        match mode {
+            OutputMode::Full | OutputMode::Json => &self.message,
+            OutputMode::FirstLine => line(),
+            OutputMode::Captures => self.captures.as_deref().unwrap_or_else(line),
+        }
+    }
+
+    /// Format the message with its type, line number and names of the matching patterns for output.
+    /// # Arguments
+    /// * `mode` - The part of the message to be printed
+    pub fn output(&self, mode: OutputMode) -> String {
+        if mode == OutputMode::Json {
+            return serde_json::to_string(self).unwrap_or_default();
+        }
+        let lines = match self.count > 1 {
+            true => format!(
+                "{}-{}, {} times",
+                self.line_number, self.last_line_number, self.count
+            ),
+            false => self.line_number.to_string(),
+        };
+        if self.patterns.is_empty() {
+            format!("{}({}): {}", self.message_type, lines, self.text(mode))
+        } else {
+            format!(
+                "{}({}) [{}]: {}",
+                self.message_type,
+                lines,
+                self.patterns.join(", "),
+                self.text(mode)
+            )
         }
     }
 }
@@ -135,18 +714,26 @@ impl Display for ProblemType {
     }
 }
 
+impl ProblemType {
+    /// Get the rank of the type to compare severities.
+    /// UNKNOWN ranks below WARNING and CRITICAL which are definite problems.
+    pub fn severity(&self) -> u8 {
+        match self {
+            ProblemType::OK => 0,
+            ProblemType::UNKNOWN => 1,
+            ProblemType::WARNING => 2,
+            ProblemType::CRITICAL => 3,
+        }
+    }
+}
+
 /// Search the log file set for specific patterns and return the matches.
 /// # Arguments
 /// * `files` - A file set of log files to be searched through
 /// * `state` - The state of the log file
-/// * `line_re` - The line pattern to determine message starts
-/// * `patterns` - Patterns to search for in the log files
-pub fn find(
-    files: &Files,
-    state: &State,
-    line_re: &Regex,
-    patterns: &Vec<Pattern>,
-) -> Result<Match, String> {
+/// * `group` - The group of the log files with the patterns to determine message starts and ends
+/// * `rules` - Patterns to search for in the log files
+pub fn find(files: &Files, state: &State, group: &FileGroup, rules: &Rules) -> Result<Match, String> {
     // Find last used log file
     let mut file_selector = files.iter().len() - 1;
     for (index, file) in files.iter().enumerate() {
@@ -163,7 +750,28 @@ pub fn find(
         last_line_number: state.line_number,
         file_size: metadata(&files[0]).unwrap().len(),
         messages: vec![],
+        excluded_count: 0,
+        suppressed_count: 0,
+        known_count: 0,
+        counts: vec![0; rules.patterns.count_patterns.len()],
+        values: vec![vec![]; rules.patterns.value_patterns.len()],
+        recoveries: vec![],
+        expected: vec![0; rules.patterns.expect_patterns.len()],
+        pending: state
+            .pending_correlations
+            .iter()
+            .filter(|pending| {
+                rules
+                    .patterns
+                    .correlations
+                    .iter()
+                    .any(|correlation| correlation.start.label() == pending.correlation)
+            })
+            .cloned()
+            .collect(),
         keep_until: Utc::now(),
+        checked: Some(Utc::now()),
+        message_indexes: HashMap::new(),
     };
 
     // Walk through all log files to current
@@ -181,18 +789,43 @@ pub fn find(
             if line_index <= state.line_number {
                 continue;
             }
-            message.line_number = line_index;
-            if line_re.is_match(&line) {
+            // Lines not continuing a message start a new one too
+            let is_continued = group.continuation_re.as_ref().map_or(true, |re| re.is_match(&line));
+            if group.line_re.is_match(&line) || !is_continued {
                 // last message has finished, analyze it
-                find_in_message(&mut message, patterns, &mut matches);
+                find_in_message(&mut message, rules, &mut matches);
                 // new message starts
                 message = Message::new();
             }
+            if message.message.is_empty() {
+                message.line_number = line_index + 1;
+            }
             message.message.push_str(&format!("{}\n", line));
             matches.lines_count += 1;
             matches.last_line_number = line_index;
+            if group.end_re.as_ref().is_some_and(|end_re| end_re.is_match(&line)) {
+                // message has finished with this line, analyze it
+                find_in_message(&mut message, rules, &mut matches);
+                message = Message::new();
+            }
         }
-        find_in_message(&mut message, patterns, &mut matches);
+        find_in_message(&mut message, rules, &mut matches);
+    }
+
+    // Raise problems for correlations exceeding their time window
+    let now = Utc::now();
+    let (expired, pending): (Vec<PendingCorrelation>, Vec<PendingCorrelation>) =
+        matches.pending.drain(..).partition(|pending| {
+            rules.patterns.correlations.iter().any(|correlation| {
+                correlation.start.label() == pending.correlation
+                    && correlation
+                        .window
+                        .is_some_and(|window| pending.started + Duration::seconds(window) < now)
+            })
+        });
+    matches.pending = pending;
+    for pending in expired {
+        matches.push_message(pending.message);
     }
     Ok(matches)
 }
@@ -200,13 +833,183 @@ pub fn find(
 /// Search patterns in single message.
 /// # Arguments
 /// * `message` - The message to search through
-/// * `patterns` - Patterns to search for in the message
-/// * `line_re` - Store matching messages in this struct
-fn find_in_message(message: &mut Message, patterns: &Vec<Pattern>, matches: &mut Match) {
-    for re in patterns {
-        if re.1.is_match(&message.message) {
-            message.message_type = re.0;
-            matches.messages.push(message.clone());
+/// * `rules` - Patterns to search for in the message
+/// * `matches` - Store matching messages in this struct
+fn find_in_message(message: &mut Message, rules: &Rules, matches: &mut Match) {
+    if message.message.is_empty() {
+        return;
+    }
+    let matched = rules.matcher.matches(&message.message);
+    let (matched_patterns, matched) = matched.split_at(rules.patterns.patterns.len());
+    let (matched_counts, matched) = matched.split_at(rules.patterns.count_patterns.len());
+    let (matched_values, matched) = matched.split_at(rules.patterns.value_patterns.len());
+    let (matched_oks, matched) = matched.split_at(rules.patterns.ok_patterns.len());
+    let (matched_expects, matched_correlations) = matched.split_at(rules.patterns.expect_patterns.len());
+    let text = &message.message;
+    let is_excluded_by = |problem_type: ProblemType| {
+        rules.patterns.excludes.iter().any(|exclude| {
+            exclude.0.map_or(true, |exclude_type| exclude_type == problem_type)
+                && exclude.1.is_match(text)
+        })
+    };
+
+    // Recoveries clear problems found before
+    for (pattern, _) in rules.patterns.ok_patterns.iter().zip(matched_oks).filter(|(_, is_match)| **is_match) {
+        if let Some(caps) = pattern.captures(&message.message) {
+            let recovery = Recovery {
+                name: pattern.name.clone(),
+                key: caps.name("key").map(|key| key.as_str().to_string()),
+            };
+            matches.clear(&recovery);
+            matches.recoveries.push(recovery);
+        }
+    }
+
+    // Problems of problem patterns and of values exceeding thresholds
+    // Exclude patterns drop problems only, counts and captured values are kept
+    let mut is_excluded = false;
+    let mut problems: Vec<(&Pattern, ProblemType, Captures)> = vec![];
+    for (pattern, _) in rules.patterns.patterns.iter().zip(matched_patterns).filter(|(_, is_match)| **is_match) {
+        if let Some(caps) = pattern.captures(&message.message) {
+            problems.push((pattern, pattern.problem_type, caps));
+        }
+    }
+    for ((index, value_pattern), _) in rules
+        .patterns
+        .value_patterns
+        .iter()
+        .enumerate()
+        .zip(matched_values)
+        .filter(|(_, is_match)| **is_match)
+    {
+        let caps = match value_pattern.1.captures(&message.message) {
+            Some(caps) => caps,
+            None => continue,
+        };
+        let value = match caps
+            .name("value")
+            .or_else(|| caps.get(1))
+            .and_then(|value| value.as_str().parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+        {
+            Some(value) => value,
+            None => continue,
+        };
+        matches.values[index].push(value);
+        let problem_type = value_pattern.0.check(value);
+        if problem_type != ProblemType::OK {
+            problems.push((&value_pattern.1, problem_type, caps));
+        }
+    }
+
+    let mut message_type: Option<ProblemType> = None;
+    for (pattern, pattern_type, caps) in problems {
+        if is_excluded_by(pattern_type) {
+            is_excluded = true;
+            continue;
+        }
+        if let Some(name) = &pattern.name {
+            if !message.patterns.contains(name) {
+                message.patterns.push(name.clone());
+            }
+        }
+        if message.key.is_none() {
+            message.key = caps.name("key").map(|key| key.as_str().to_string());
+        }
+        message_type = match message_type {
+            Some(problem_type)
+                if rules.patterns.policy == MatchPolicy::First
+                    || problem_type.severity() >= pattern_type.severity() =>
+            {
+                Some(problem_type)
+            }
+            _ => {
+                let groups: Vec<&str> = caps
+                    .iter()
+                    .skip(1)
+                    .flatten()
+                    .map(|group| group.as_str())
+                    .collect();
+                message.captures = match groups.is_empty() {
+                    true => None,
+                    false => Some(groups.join(" ")),
+                };
+                message.line = match pattern.scope {
+                    Scope::Lines => pattern.matching_text(text).map(String::from),
+                    _ => None,
+                };
+                Some(pattern_type)
+            }
+        };
+    }
+    // Messages reported by another pattern are not counted as excluded
+    let is_reported = message_type.is_some();
+    if let Some(problem_type) = message_type {
+        message.message_type = problem_type;
+        let mut problem = message.clone();
+        problem.identify();
+        matches.push_message(problem);
+    }
+
+    for (index, is_match) in matched_counts.iter().enumerate() {
+        if *is_match {
+            matches.counts[index] += 1;
+        }
+    }
+    if is_excluded && !is_reported {
+        matches.excluded_count += 1;
+    }
+
+    for (index, is_match) in matched_expects.iter().enumerate() {
+        if *is_match {
+            matches.expected[index] += 1;
+        }
+    }
+
+    // Correlations end by the end pattern, start by the start pattern or expire after some messages
+    for pending in matches.pending.iter_mut() {
+        pending.messages += 1;
+    }
+    for (correlation, is_match) in rules.patterns.correlations.iter().zip(matched_correlations.chunks(2)) {
+        let label = correlation.start.label();
+        if is_match[1] {
+            if let Some(key) = correlation.end.capture_key(&message.message) {
+                matches
+                    .pending
+                    .retain(|pending| pending.correlation != label || pending.key != key);
+            }
+        }
+        if is_match[0] {
+            if let Some(key) = correlation.start.capture_key(&message.message) {
+                let is_pending = matches
+                    .pending
+                    .iter()
+                    .any(|pending| pending.correlation == label && pending.key == key);
+                if !is_pending {
+                    let mut start_message = message.clone();
+                    start_message.message_type = correlation.start.problem_type;
+                    start_message.patterns = correlation.start.name.iter().cloned().collect();
+                    start_message.key = Some(key.clone());
+                    start_message.identify();
+                    matches.pending.push(PendingCorrelation {
+                        correlation: label.to_string(),
+                        key,
+                        messages: 0,
+                        started: Utc::now(),
+                        message: start_message,
+                    });
+                }
+            }
+        }
+        if let Some(limit) = correlation.messages {
+            let (expired, pending): (Vec<PendingCorrelation>, Vec<PendingCorrelation>) = matches
+                .pending
+                .drain(..)
+                .partition(|pending| pending.correlation == label && pending.messages >= limit);
+            matches.pending = pending;
+            for pending in expired {
+                matches.push_message(pending.message);
+            }
         }
     }
 }
@@ -227,28 +1030,498 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn test() {
-        // given
-        let mut message = Message {
-            line_number: 1,
-            message_type: ProblemType::OK,
-            message: "abc 123".into(),
-        };
-        let patterns = vec![(ProblemType::CRITICAL, Regex::new(r"123").unwrap())];
-        let mut matches = Match {
+    fn new_match() -> Match {
+        Match {
             path: std::path::PathBuf::new(),
             lines_count: 0,
             last_line_number: 1,
             file_size: 123,
             messages: vec![],
+            excluded_count: 0,
+            suppressed_count: 0,
+            known_count: 0,
+            counts: vec![0],
+            values: vec![vec![]],
+            recoveries: vec![],
+            expected: vec![],
+            pending: vec![],
             keep_until: Utc::now(),
-        };
+            checked: None,
+            message_indexes: HashMap::new(),
+        }
+    }
+
+    fn new_message(text: &str) -> Message {
+        Message {
+            line_number: 1,
+            message_type: ProblemType::OK,
+            message: text.into(),
+            ..Message::new()
+        }
+    }
+
+    #[test]
+    fn test() {
+        // given
+        let mut message = new_message("abc 123");
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![Pattern::parse(r"123", ProblemType::CRITICAL).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
         // when
-        find_in_message(&mut message, &patterns, &mut matches);
+        find_in_message(&mut message, &rules, &mut matches);
 
         // then
         assert_eq!(message.message_type, ProblemType::CRITICAL);
         assert_eq!(matches.messages.len(), 1);
     }
+
+    #[test]
+    fn test_policy() {
+        // given
+        let message = new_message("Exception: timeout");
+        let patterns = || {
+            vec![
+                Pattern::parse(r"timeout", ProblemType::WARNING).unwrap(),
+                Pattern::parse(r"exception=Exception", ProblemType::CRITICAL).unwrap(),
+                Pattern::parse(r"Exception: \w+", ProblemType::CRITICAL).unwrap(),
+            ]
+        };
+
+        for (policy, problem_type) in [
+            (MatchPolicy::Highest, ProblemType::CRITICAL),
+            (MatchPolicy::First, ProblemType::WARNING),
+        ] {
+            let rules = Rules::new(RulePatterns {
+                patterns: patterns(),
+                policy,
+                ..Default::default()
+            })
+            .unwrap();
+            let mut matches = new_match();
+
+            // when
+            find_in_message(&mut message.clone(), &rules, &mut matches);
+
+            // then
+            assert_eq!(matches.messages.len(), 1);
+            assert_eq!(matches.messages[0].message_type, problem_type);
+            assert_eq!(
+                matches.messages[0].patterns,
+                vec!["exception"]
+            );
+            assert_eq!(
+                matches.messages[0].output(OutputMode::Full),
+                format!("{}(1) [exception]: Exception: timeout", problem_type)
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_policy() {
+        // given
+        let message = new_message("log buffer overflow: OutOfMemoryError");
+        let patterns = || {
+            vec![
+                Pattern::parse(r"log buffer overflow", ProblemType::UNKNOWN).unwrap(),
+                Pattern::parse(r"OutOfMemoryError", ProblemType::CRITICAL).unwrap(),
+            ]
+        };
+
+        for (policy, problem_type) in [
+            (MatchPolicy::Highest, ProblemType::CRITICAL),
+            (MatchPolicy::First, ProblemType::UNKNOWN),
+        ] {
+            let rules = Rules::new(RulePatterns {
+                patterns: patterns(),
+                policy,
+                ..Default::default()
+            })
+            .unwrap();
+            let mut matches = new_match();
+
+            // when
+            find_in_message(&mut message.clone(), &rules, &mut matches);
+            find_in_message(&mut new_message("OutOfMemoryError"), &rules, &mut matches);
+
+            // then
+            assert_eq!(matches.messages[0].message_type, problem_type);
+            assert_eq!(matches.any_unknown(), problem_type == ProblemType::UNKNOWN);
+            assert_eq!(matches.messages[1].message_type, ProblemType::CRITICAL);
+        }
+    }
+
+    #[test]
+    fn test_exclude() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![
+                Pattern::parse(r"123", ProblemType::WARNING).unwrap(),
+                Pattern::parse(r"Exception", ProblemType::CRITICAL).unwrap(),
+            ],
+            excludes: vec![(Some(ProblemType::CRITICAL), Regex::new(r"harmless").unwrap())],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for text in ["Exception: harmless 123", "Exception: harmless"] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        assert_eq!(matches.messages.len(), 1);
+        assert_eq!(matches.messages[0].message_type, ProblemType::WARNING);
+        assert_eq!(matches.excluded_count, 1);
+    }
+
+    #[test]
+    fn test_count() {
+        // given
+        let mut message = new_message("read timeout");
+        let rules = Rules::new(RulePatterns {
+            count_patterns: vec![(
+                Thresholds::parse("5", "50").unwrap(),
+                None,
+                Pattern::parse(r"timeout", ProblemType::OK).unwrap(),
+            )],
+            excludes: vec![(None, Regex::new(r"read").unwrap())],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        find_in_message(&mut message, &rules, &mut matches);
+
+        // then
+        assert!(matches.messages.is_empty());
+        assert_eq!(matches.counts, vec![1]);
+        assert_eq!(matches.excluded_count, 0);
+    }
+
+    #[test]
+    fn test_value() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            value_patterns: vec![(
+                Thresholds::parse("500", "1000").unwrap(),
+                Pattern::parse(r"took=took (?P<value>\w+)ms", ProblemType::OK).unwrap(),
+            )],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for text in [
+            "request took 20ms",
+            "request took 700ms",
+            "request took 1200ms",
+            "took long",
+            "request took NaNms",
+            "request took infms",
+        ] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        assert_eq!(matches.values, vec![vec![20.0, 700.0, 1200.0]]);
+        assert_eq!(matches.messages.len(), 2);
+        assert_eq!(matches.messages[0].message_type, ProblemType::WARNING);
+        assert_eq!(matches.messages[1].message_type, ProblemType::CRITICAL);
+        assert_eq!(matches.messages[1].patterns, vec!["took"]);
+    }
+
+    #[test]
+    fn test_expect() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            expect_patterns: vec![(1200, Pattern::parse(r"run finished", ProblemType::CRITICAL).unwrap())],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = Match {
+            expected: vec![0],
+            ..new_match()
+        };
+
+        // when
+        for text in ["run started", "run finished"] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        assert!(matches.messages.is_empty());
+        assert_eq!(matches.expected, vec![1]);
+    }
+
+    #[test]
+    fn test_correlation() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            correlations: vec![Correlation {
+                start: Pattern::parse(r"tx=tx (\d+) started", ProblemType::CRITICAL).unwrap(),
+                end: Pattern::parse(r"tx (\d+) committed", ProblemType::OK).unwrap(),
+                messages: Some(2),
+                window: None,
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for text in ["tx 1 started", "tx 2 started", "tx 1 committed", "tx 3 started", "other"] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        assert_eq!(matches.messages.len(), 1);
+        assert_eq!(matches.messages[0].message, "tx 2 started");
+        assert_eq!(matches.messages[0].message_type, ProblemType::CRITICAL);
+        assert_eq!(matches.pending.len(), 1);
+        assert_eq!(matches.pending[0].key, "3");
+    }
+
+    #[test]
+    fn test_correlation_idle_run() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            correlations: vec![Correlation {
+                start: Pattern::parse(r"tx=tx (\d+) started", ProblemType::CRITICAL).unwrap(),
+                end: Pattern::parse(r"tx (\d+) committed", ProblemType::OK).unwrap(),
+                messages: Some(2),
+                window: None,
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+        find_in_message(&mut new_message("tx 1 started"), &rules, &mut matches);
+
+        // when
+        for _ in 0..3 {
+            find_in_message(&mut Message::new(), &rules, &mut matches);
+        }
+
+        // then
+        assert!(matches.messages.is_empty());
+        assert_eq!(matches.pending.len(), 1);
+        assert_eq!(matches.pending[0].messages, 0);
+    }
+
+    #[test]
+    fn test_recovery() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![
+                Pattern::parse(r"db=connection to (?P<key>\w+) lost", ProblemType::CRITICAL).unwrap(),
+                Pattern::parse(r"oom=OutOfMemoryError", ProblemType::CRITICAL).unwrap(),
+            ],
+            ok_patterns: vec![Pattern::parse(r"db=connection to (?P<key>\w+) restored", ProblemType::OK).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for text in [
+            "connection to db1 lost",
+            "connection to db2 lost",
+            "OutOfMemoryError",
+            "connection to db1 restored",
+        ] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        assert_eq!(matches.messages.len(), 2);
+        assert_eq!(matches.messages[0].key.as_deref(), Some("db2"));
+        assert_eq!(matches.messages[1].patterns, vec!["oom"]);
+        assert_eq!(
+            matches.recoveries,
+            vec![Recovery {
+                name: Some("db".into()),
+                key: Some("db1".into())
+            }]
+        );
+    }
+
+    #[test]
+    fn test_output() {
+        // given
+        let mut message = new_message("java.lang.IllegalStateException: closed\n\tat Foo.bar()\n");
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![
+                Pattern::parse(r"(\w+Exception): (.*)", ProblemType::CRITICAL).unwrap(),
+                Pattern::parse(r"Foo", ProblemType::WARNING).unwrap(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        find_in_message(&mut message, &rules, &mut matches);
+
+        // then
+        let message = &matches.messages[0];
+        assert_eq!(message.text(OutputMode::Full), "java.lang.IllegalStateException: closed\n\tat Foo.bar()\n");
+        assert_eq!(message.text(OutputMode::FirstLine), "java.lang.IllegalStateException: closed");
+        assert_eq!(message.text(OutputMode::Captures), "IllegalStateException closed");
+        let output = matches.output(OutputMode::Json);
+        assert_eq!(output.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json["messages"][0]["message"], message.text(OutputMode::Full));
+        assert_eq!(json["messages"][0]["captures"], "IllegalStateException closed");
+    }
+
+    #[test]
+    fn test_repetitions() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![Pattern::parse(r"Connection refused", ProblemType::CRITICAL).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for (line_number, port) in [(3, 5432), (7, 5433), (9, 5432)] {
+            let mut message = new_message(&format!("Connection refused to 10.0.0.5:{}", port));
+            message.line_number = line_number;
+            find_in_message(&mut message, &rules, &mut matches);
+        }
+
+        // then
+        assert_eq!(matches.messages.len(), 1);
+        assert_eq!(matches.count_critical(), 3);
+        assert_eq!(
+            matches.messages[0].output(OutputMode::Full),
+            "CRITICAL(3-9, 3 times): Connection refused to 10.0.0.5:5432"
+        );
+    }
+
+    #[test]
+    fn test_repetitions_after_recovery() {
+        // given
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![
+                Pattern::parse(r"db=connection to (?P<key>\w+) lost", ProblemType::CRITICAL).unwrap(),
+                Pattern::parse(r"oom=OutOfMemoryError", ProblemType::CRITICAL).unwrap(),
+            ],
+            ok_patterns: vec![Pattern::parse(r"db=connection to (?P<key>\w+) restored", ProblemType::OK).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        for text in [
+            "connection to main lost",
+            "OutOfMemoryError",
+            "connection to main restored",
+            "OutOfMemoryError",
+            "connection to main lost",
+        ] {
+            find_in_message(&mut new_message(text), &rules, &mut matches);
+        }
+
+        // then
+        let messages: Vec<(&str, usize)> = matches
+            .messages
+            .iter()
+            .map(|message| (message.message.as_str(), message.count))
+            .collect();
+        assert_eq!(messages, vec![("OutOfMemoryError", 2), ("connection to main lost", 1)]);
+    }
+
+    #[test]
+    fn test_scope() {
+        // given
+        let text = "ERROR request failed\nCaused by: timeout\nERROR retry\n";
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![
+                Pattern::parse(r"/f=^ERROR.*$", ProblemType::WARNING).unwrap(),
+                Pattern::parse(r"cause/e=^Caused by: (.*)$", ProblemType::CRITICAL).unwrap(),
+                Pattern::parse(r"/f=retry", ProblemType::CRITICAL).unwrap(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut matches = new_match();
+
+        // when
+        find_in_message(&mut new_message(text), &rules, &mut matches);
+
+        // then
+        let message = &matches.messages[0];
+        assert_eq!(message.message_type, ProblemType::CRITICAL);
+        assert_eq!(message.patterns, vec!["cause"]);
+        assert_eq!(message.text(OutputMode::FirstLine), "Caused by: timeout");
+        assert_eq!(message.text(OutputMode::Captures), "timeout");
+    }
+
+    #[test]
+    fn test_pattern_name() {
+        let pattern = Pattern::parse(r"oom=java\.lang\.OutOfMemoryError", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("oom"));
+        assert_eq!(pattern.re.as_str(), r"java\.lang\.OutOfMemoryError");
+
+        let pattern = Pattern::parse(r"=key=value", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name, None);
+        assert_eq!(pattern.label(), "key=value");
+
+        let pattern = Pattern::parse(r"(a=b)", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name, None);
+
+        let pattern = Pattern::parse(r"oom/i=outofmemory", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("oom"));
+        assert!(pattern.re.is_match("java.lang.OutOfMemoryError"));
+
+        let pattern = Pattern::new(r"status=500", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name, None);
+        assert_eq!(pattern.label(), "status=500");
+        assert!(!pattern.re.is_match("500"));
+
+        let pattern = Pattern::parse(r"/s=a.b", ProblemType::CRITICAL).unwrap();
+        assert_eq!(pattern.name, None);
+        assert_eq!(pattern.label(), "a.b");
+        assert_eq!(pattern.re.as_str(), "(?s)a.b");
+        assert!(pattern.re.is_match("a\nb"));
+
+        assert!(Pattern::parse(r"oom/q=outofmemory", ProblemType::CRITICAL).is_err());
+    }
+
+    #[test]
+    fn test_line_number() {
+        // given
+        let path = std::env::temp_dir().join("check_logmultiline_line_number.log");
+        std::fs::write(&path, "INFO started\nERROR failed\n  details\nERROR failed again\n").unwrap();
+        let files = vec![path.clone()];
+        let state = State::new(path, None);
+        let rules = Rules::new(RulePatterns {
+            patterns: vec![Pattern::parse(r"ERROR", ProblemType::CRITICAL).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // when
+        let group = FileGroup {
+            files: vec![files.clone()],
+            line_re: Regex::new(r"^[A-Z]").unwrap(),
+            end_re: None,
+            continuation_re: None,
+            rules: None,
+        };
+        let matches = find(&files, &state, &group, &rules).unwrap();
+
+        // then
+        let line_numbers: Vec<i64> = matches.messages.iter().map(|message| message.line_number).collect();
+        assert_eq!(line_numbers, vec![2, 4]);
+    }
 }
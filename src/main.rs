@@ -6,21 +6,33 @@
 
 #[macro_use]
 extern crate clap;
+extern crate aho_corasick;
 extern crate chrono;
+extern crate chrono_tz;
 extern crate directories;
 extern crate fs2;
 extern crate regex;
 extern crate serde;
 extern crate serde_json;
+extern crate toml;
 
 mod args;
+mod fingerprint;
 mod logfile;
+mod maintenance;
+mod matcher;
+mod preset;
 mod state;
+mod stats;
+mod threshold;
 
-use args::Args;
+use args::{Args, Command, Files, StateAction, StateArgs};
 use chrono::{prelude::*, Duration};
-use logfile::{find, Match, ProblemType, file_modified};
+use logfile::{find, Match, Message, OutputMode, ProblemType, file_modified};
+use maintenance::MaintenanceWindow;
 use state::{State, StateLoader};
+use stats::{Stats, STAT_NAMES};
+use std::collections::HashMap;
 use std::process::exit;
 
 /// The name of this check printed for result output.
@@ -34,12 +46,157 @@ fn unknown(msg: &str) -> ! {
     exit(ProblemType::UNKNOWN as i32);
 }
 
+/// Quotes a performance data label and replaces characters not allowed in labels.
+/// # Arguments
+/// * `label` - The label to be quoted
+fn perfdata_label(label: &str) -> String {
+    format!("'{}'", label.replace(['\'', '='], "_"))
+}
+
+/// Get the seconds since the last modification of a log file. Modification dates in the future
+/// count as just modified.
+/// # Arguments
+/// * `now` - The date of the current run
+/// * `modified` - The date of the last modification
+fn file_age(now: DateTime<Utc>, modified: DateTime<Utc>) -> i64 {
+    (now - modified).num_seconds().max(0)
+}
+
+/// Drop the problem messages suppressed by maintenance windows and, in learning mode, the learned
+/// messages and count them. Suppressed messages are not learned, so they raise their problem when
+/// they are found again after the window.
+/// # Arguments
+/// * `matchh` - The matches in a log file
+/// * `state` - The state of the log file
+/// * `learn` - The problem raised for messages never seen before in learning mode
+/// * `now` - The date of the current run
+/// * `is_suppressed` - Tests if a message is suppressed by a maintenance window
+fn drop_messages(
+    matchh: &mut Match,
+    state: &mut State,
+    learn: Option<ProblemType>,
+    now: DateTime<Utc>,
+    is_suppressed: impl Fn(&Message) -> bool,
+) {
+    let mut suppressed_count = 0;
+    matchh.messages.retain(|message| match is_suppressed(message) {
+        true => {
+            suppressed_count += message.count;
+            false
+        }
+        false => true,
+    });
+    matchh.suppressed_count = suppressed_count;
+
+    // Raise problems for messages never seen before and count learned messages only
+    if let Some(learn_status) = learn {
+        let mut known_count = 0;
+        matchh.messages.retain_mut(|message| match state.learn(message, now) {
+            true => {
+                // The learn status raises but never lowers the problem of a new message
+                if learn_status.severity() > message.message_type.severity() {
+                    message.message_type = learn_status;
+                }
+                true
+            }
+            false => {
+                known_count += message.count;
+                false
+            }
+        });
+        matchh.known_count = known_count;
+    }
+}
+
+/// Exits program with an error message for the state subcommand.
+/// # Arguments
+/// * `msg` - The error message to be printed out
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1);
+}
+
 fn main() {
     // Parse and validate command line arguments
-    let args = match Args::get() {
-        Ok(args) => args,
+    match Command::get() {
+        Ok(Command::Check(args)) => check(*args),
+        Ok(Command::State(Ok(args))) => manage_state(args),
+        Ok(Command::State(Err(e))) => fail(&format!("Could not parse state arguments: {}", e)),
         Err(e) => unknown(&format!("Could not parse command line arguments: {}", e)),
     };
+}
+
+/// Lists or changes the states in the state file and exits.
+/// # Arguments
+/// * `args` - The arguments of the state subcommand
+fn manage_state(args: StateArgs) -> ! {
+    let mut state_loader = StateLoader::new(args.state_path.as_path());
+    let mut statedoc = match state_loader.load() {
+        Ok(states) => states,
+        Err(e) => fail(&format!("Could not load state: {}", e)),
+    };
+
+    let mut output = String::new();
+    let mut changed_count = 0;
+    for state in statedoc.states.iter_mut().filter(|state| args.applies_to(state)) {
+        match &args.action {
+            StateAction::List => {
+                if state.kept_matches.is_empty() && state.learned.is_empty() {
+                    continue;
+                }
+                output.push_str(&format!("File: {}", state.path.to_string_lossy()));
+                if let Some(check) = &state.check {
+                    output.push_str(&format!(" (check {})", check));
+                }
+                output.push('\n');
+                for kept_match in &state.kept_matches {
+                    for message in &kept_match.messages {
+                        output.push_str(&format!(
+                            "Kept {} until {}: {}\n",
+                            message.fingerprint,
+                            kept_match.keep_until.to_rfc3339_opts(SecondsFormat::Secs, true),
+                            message.output(OutputMode::FirstLine)
+                        ));
+                    }
+                }
+                for learned in &state.learned {
+                    output.push_str(&format!(
+                        "Learned {} {} times from {} to {}: {}\n",
+                        learned.fingerprint,
+                        learned.count,
+                        learned.first_seen.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        learned.last_seen.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        learned.message
+                    ));
+                }
+            }
+            StateAction::Forget(fingerprints) => changed_count += state.forget(fingerprints),
+            StateAction::Ack { fingerprints, before } => changed_count += state.ack(fingerprints, *before),
+        }
+    }
+    let summary = match args.action {
+        StateAction::List => None,
+        StateAction::Forget(_) => Some(format!("Forgot {} learned messages\n", changed_count)),
+        StateAction::Ack { .. } => Some(format!("Acknowledged {} kept messages\n", changed_count)),
+    };
+    if let Some(summary) = summary {
+        if let Err(e) = state_loader.save(&statedoc) {
+            fail(&format!("Could not save state file: {}", e));
+        }
+        output.push_str(&summary);
+    }
+    if let Err(e) = state_loader.close_file() {
+        fail(&format!("Could not close state file: {}", e));
+    }
+
+    print!("{}", output);
+    exit(0);
+}
+
+/// Checks the log files, prints the results and exits with the status code.
+/// # Arguments
+/// * `args` - The arguments of the check
+fn check(args: Args) -> ! {
 
     // Get state of log file searches
     let mut state_loader = StateLoader::new(args.state_path.as_path());
@@ -49,40 +206,126 @@ fn main() {
     };
 
     let mut matches: Vec<Match> = vec![];
+    let mut age_results: Vec<(&Files, DateTime<Utc>, ProblemType)> = vec![];
+    let mut pattern_counts: HashMap<String, usize> = HashMap::new();
+    let now = Utc::now();
+    let maintenance: Vec<&MaintenanceWindow> = args
+        .maintenance
+        .iter()
+        .filter(|window| window.is_active(now))
+        .collect();
+    let is_pattern_suppressed = |label: &str| maintenance.iter().any(|window| window.applies_to_pattern(label));
+    let is_file_suppressed = |path: &str| maintenance.iter().any(|window| window.applies_to_file(path));
 
     // Iterate through log files
-    for file in &args.files {
+    for (file, group, rules) in args.file_sets() {
         // Get the state of the current log file
-        let mut state = match statedoc
+        let state = match statedoc
             .states
             .iter_mut()
-            .find(|state| state.path == file[0])
+            .find(|state| state.path == file[0] && state.check == args.check)
         {
             Some(state) => state,
             None => {
-                let state = State::new(file[0].clone());
+                let state = State::new(file[0].clone(), args.check.clone());
                 statedoc.states.push(state);
                 statedoc.states.last_mut().unwrap()
             }
         };
 
         // Search the log file for defined patterns
-        let mut matchh = match find(&file, state, &args.line_re, &args.patterns) {
+        let mut matchh = match find(file, state, group, rules) {
             Ok(result) => result,
             Err(e) => unknown(&format!("Could not check log file: {}", e)),
         };
 
+        // Count the messages of named patterns before learned and suppressed messages are dropped
+        for message in &matchh.messages {
+            for name in &message.patterns {
+                *pattern_counts.entry(name.clone()).or_default() += message.count;
+            }
+        }
+
+        // Count problems during maintenance windows and learned messages only
+        // Messages are suppressed if the window applies to the log file or to all their patterns
+        let is_log_suppressed = is_file_suppressed(&file[0].to_string_lossy());
+        drop_messages(&mut matchh, state, args.learn, now, |message| {
+            is_log_suppressed
+                || (!message.patterns.is_empty()
+                    && message.patterns.iter().all(|pattern| is_pattern_suppressed(pattern)))
+        });
+
         // Clean up expired kept messages
-        let now = Utc::now();
         state.kept_matches.retain(|matchh| matchh.keep_until >= now);
 
+        // Clear kept messages by recoveries
+        for recovery in &matchh.recoveries {
+            for kept_match in state.kept_matches.iter_mut() {
+                kept_match
+                    .messages
+                    .retain(|message| !recovery.clears(message));
+            }
+        }
+        state
+            .kept_matches
+            .retain(|kept_match| !kept_match.messages.is_empty());
+
         // Keep messages in state
+        // Repetitions of kept messages are moved into the new messages to keep them once only
         if args.keep_status > 0 && matchh.messages.iter().len() > 0 {
             matchh.keep_until = now + Duration::seconds(args.keep_status);
-            state.kept_matches.push(matchh.clone());
+            let mut kept_match = matchh.clone();
+            for message in kept_match.messages.iter_mut() {
+                for previous_match in state.kept_matches.iter_mut() {
+                    if let Some(index) = previous_match
+                        .messages
+                        .iter()
+                        .position(|previous| previous.is_repeated_by(message))
+                    {
+                        let mut previous = previous_match.messages.remove(index);
+                        previous.repeat(message);
+                        *message = previous;
+                    }
+                }
+            }
+            state
+                .kept_matches
+                .retain(|kept_match| !kept_match.messages.is_empty());
+            state.kept_matches.push(kept_match);
+        }
+
+        // Remember match counts of rate patterns
+        // Match counts older than the largest window are not needed by any rate pattern
+        let max_window = args.rules.patterns.count_patterns.iter().filter_map(|pattern| pattern.1).max();
+        state.prune_hits(now - Duration::seconds(max_window.unwrap_or_default()));
+        for (index, pattern) in args.rules.patterns.count_patterns.iter().enumerate() {
+            if let Some(window) = pattern.1 {
+                let hits = state.pattern_hits_mut(pattern.2.label(), window);
+                hits.hits.retain(|hit| hit.0 >= now - Duration::seconds(window));
+                if matchh.counts[index] > 0 {
+                    hits.hits.push((now, matchh.counts[index]));
+                }
+            }
+        }
+
+        // Remember captured values of value patterns
+        if let Some(window) = args.value_window {
+            state.prune_values(now - Duration::seconds(window));
+            for (index, pattern) in args.rules.patterns.value_patterns.iter().enumerate() {
+                state.add_values(pattern.1.label(), now, &matchh.values[index]);
+            }
+        }
+
+        // Remember last matches of expect patterns
+        for (index, pattern) in args.rules.patterns.expect_patterns.iter().enumerate() {
+            let seen = state.pattern_seen_mut(pattern.1.label(), now);
+            if matchh.expected[index] > 0 {
+                seen.last_seen = now;
+            }
         }
 
         // Fill up state
+        state.pending_correlations = matchh.pending.clone();
         state.line_number = matchh.last_line_number;
         state.size = matchh.file_size;
         state.modified = match file_modified(file[0].as_path()) {
@@ -90,6 +333,17 @@ fn main() {
             Err(e) => unknown(&e),
         };
 
+        // Check the time since the last modification of log files with age thresholds
+        // Thresholds are not checked during maintenance windows of the log file
+        if let Some(thresholds) = args.max_age(file) {
+            let modified = DateTime::<Utc>::from(state.modified);
+            let problem_type = match is_file_suppressed(&file[0].to_string_lossy()) {
+                true => ProblemType::OK,
+                false => thresholds.check(file_age(now, modified) as f64),
+            };
+            age_results.push((file, modified, problem_type));
+        }
+
         matches.push(matchh);
     }
 
@@ -102,24 +356,170 @@ fn main() {
     }
 
     // Check kept messages
+    let is_checked_state = |state: &&State| {
+        state.check == args.check && args.contains_file(&state.path)
+    };
     let kept_matches: Vec<&Match> = statedoc
         .states
         .iter()
-        .filter(|state| args.files.iter().any(|file| state.path == file[0]))
-        .map(|state| &state.kept_matches)
-        .flatten()
+        .filter(is_checked_state)
+        .flat_map(|state| &state.kept_matches)
         .collect();
     let is_kept_critical = kept_matches.iter().any(|matches| matches.any_critical());
     let is_kept_warning = kept_matches.iter().any(|matches| matches.any_warning());
+    let is_kept_unknown = kept_matches.iter().any(|matches| matches.any_unknown());
 
     // Check current results and set status code
     let is_critical = matches.iter().any(|matchh| matchh.any_critical());
     let is_warning = matches.iter().any(|matchh| matchh.any_warning());
+    let is_unknown = matches.iter().any(|matchh| matchh.any_unknown());
+
+    // Check count thresholds
+    // Rate patterns count the matches of previous runs within their time window too
+    // Thresholds are not checked during maintenance windows of the pattern
+    let count_results: Vec<(String, usize, ProblemType)> = args
+        .rules
+        .patterns
+        .count_patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| {
+            let count = match pattern.1 {
+                Some(window) => statedoc
+                    .states
+                    .iter()
+                    .filter(is_checked_state)
+                    .flat_map(|state| &state.pattern_hits)
+                    .filter(|hits| hits.is_for(pattern.2.label(), window))
+                    .fold(0, |count, hits| {
+                        count + hits.count_since(now - Duration::seconds(window))
+                    }),
+                None => matches
+                    .iter()
+                    .fold(0, |count, matchh| count + matchh.counts[index]),
+            };
+            let problem_type = match is_pattern_suppressed(pattern.2.label()) {
+                true => ProblemType::OK,
+                false => pattern.0.check(count as f64),
+            };
+            (pattern.2.label().to_string(), count, problem_type)
+        })
+        .collect();
+    let is_count_critical = count_results
+        .iter()
+        .any(|result| result.2 == ProblemType::CRITICAL);
+    let is_count_warning = count_results
+        .iter()
+        .any(|result| result.2 == ProblemType::WARNING);
 
-    let code = if is_critical || is_kept_critical {
+    // Check statistics of captured values
+    // With a value window the values of previous runs within the window are used too
+    // Thresholds are not checked during maintenance windows of the value pattern
+    let value_stats: Vec<(&str, Stats)> = args
+        .rules
+        .patterns
+        .value_patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| {
+            let values: Vec<f64> = match args.value_window {
+                Some(window) => statedoc
+                    .states
+                    .iter()
+                    .filter(is_checked_state)
+                    .flat_map(|state| &state.pattern_values)
+                    .filter(|values| values.pattern == pattern.1.label())
+                    .flat_map(|values| &values.values)
+                    .filter(|value| value.0 >= now - Duration::seconds(window))
+                    .map(|value| value.1)
+                    .collect(),
+                None => matches
+                    .iter()
+                    .flat_map(|matchh| matchh.values[index].iter().copied())
+                    .collect(),
+            };
+            (pattern.1.label(), Stats::new(&values))
+        })
+        .collect();
+    let stat_results: Vec<(String, f64, ProblemType)> = args
+        .stat_thresholds
+        .iter()
+        .filter_map(|(stat, thresholds, name)| {
+            let stats = &value_stats.iter().find(|stats| stats.0 == name)?.1;
+            let value = stats.get(stat)?;
+            let problem_type = match is_pattern_suppressed(name) {
+                true => ProblemType::OK,
+                false => thresholds.check(value),
+            };
+            Some((format!("{} of '{}'", stat, name), value, problem_type))
+        })
+        .collect();
+    let is_stat_critical = stat_results
+        .iter()
+        .any(|result| result.2 == ProblemType::CRITICAL);
+    let is_stat_warning = stat_results
+        .iter()
+        .any(|result| result.2 == ProblemType::WARNING);
+
+    // Check last matches of expect patterns
+    // The latest match in any log file counts
+    // Missing matches raise no problem during maintenance windows of the pattern
+    let expect_results: Vec<(&str, DateTime<Utc>, ProblemType)> = args
+        .rules
+        .patterns
+        .expect_patterns
+        .iter()
+        .map(|(within, pattern)| {
+            let last_seen = statedoc
+                .states
+                .iter()
+                .filter(is_checked_state)
+                .flat_map(|state| &state.pattern_seen)
+                .filter(|seen| seen.pattern == pattern.label())
+                .map(|seen| seen.last_seen)
+                .max()
+                .unwrap_or(now);
+            let is_missing = now - last_seen > Duration::seconds(*within);
+            let problem_type = match is_missing && !is_pattern_suppressed(pattern.label()) {
+                true => pattern.problem_type,
+                false => ProblemType::OK,
+            };
+            (pattern.label(), last_seen, problem_type)
+        })
+        .collect();
+    let is_expect_critical = expect_results
+        .iter()
+        .any(|result| result.2 == ProblemType::CRITICAL);
+    let is_expect_warning = expect_results
+        .iter()
+        .any(|result| result.2 == ProblemType::WARNING);
+
+    let is_age_critical = age_results
+        .iter()
+        .any(|result| result.2 == ProblemType::CRITICAL);
+    let is_age_warning = age_results
+        .iter()
+        .any(|result| result.2 == ProblemType::WARNING);
+
+    // Definite problems take precedence over an unreliable monitoring
+    let code = if is_critical
+        || is_kept_critical
+        || is_count_critical
+        || is_stat_critical
+        || is_expect_critical
+        || is_age_critical
+    {
         ProblemType::CRITICAL
-    } else if is_warning || is_kept_warning {
+    } else if is_warning
+        || is_kept_warning
+        || is_count_warning
+        || is_stat_warning
+        || is_expect_warning
+        || is_age_warning
+    {
         ProblemType::WARNING
+    } else if is_unknown || is_kept_unknown {
+        ProblemType::UNKNOWN
     } else {
         ProblemType::OK
     };
@@ -135,6 +535,9 @@ fn main() {
     let kept_criticals_count = kept_matches
         .iter()
         .fold(0, |count, matchh| count + matchh.count_critical());
+    let kept_unknowns_count = kept_matches
+        .iter()
+        .fold(0, |count, matchh| count + matchh.count_unknown());
 
     let warnings_count = matches
         .iter()
@@ -142,39 +545,205 @@ fn main() {
     let criticals_count = matches
         .iter()
         .fold(0, |count, matchh| count + matchh.count_critical());
+    let unknowns_count = matches
+        .iter()
+        .fold(0, |count, matchh| count + matchh.count_unknown());
     let lines_count = matches
         .iter()
         .fold(0, |count, matchh| count + matchh.lines_count);
     let files_count = matches.iter().len();
+    let excluded_count = matches
+        .iter()
+        .fold(0, |count, matchh| count + matchh.excluded_count);
 
     msg.push_str(&format!(
-        "{} criticals and {} warnings - new: {} criticals and {} warnings in {} lines of {} files\n",
-        kept_criticals_count, kept_warnings_count, criticals_count, warnings_count, lines_count, files_count
+        "{} criticals, {} warnings and {} unknowns - new: {} criticals, {} warnings and {} unknowns in {} lines of {} files\n",
+        kept_criticals_count,
+        kept_warnings_count,
+        kept_unknowns_count,
+        criticals_count,
+        warnings_count,
+        unknowns_count,
+        lines_count,
+        files_count
     ));
 
+    // Print exceeded count thresholds
+    for (pattern, count, problem_type) in count_results.iter() {
+        if *problem_type != ProblemType::OK {
+            msg.push_str(&format!(
+                "{}: {} matches of '{}'\n",
+                problem_type, count, pattern
+            ));
+        }
+    }
+
+    // Print exceeded statistic thresholds
+    for (stat, value, problem_type) in stat_results.iter() {
+        if *problem_type != ProblemType::OK {
+            msg.push_str(&format!("{}: {} is {}\n", problem_type, stat, value));
+        }
+    }
+
+    // Print missing matches of expect patterns
+    for (pattern, last_seen, problem_type) in expect_results.iter() {
+        if *problem_type != ProblemType::OK {
+            msg.push_str(&format!(
+                "{}: no match of '{}' since {}\n",
+                problem_type,
+                pattern,
+                last_seen.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+    }
+
+    // Print stale log files
+    for (file, modified, problem_type) in age_results.iter() {
+        if *problem_type != ProblemType::OK {
+            msg.push_str(&format!(
+                "{}: {} not modified since {}\n",
+                problem_type,
+                file[0].to_string_lossy(),
+                modified.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+    }
+
     // Print messages
     // Kept messages contains new messages here too
     if args.keep_status > 0 {
         for matches in kept_matches.iter() {
-            if matches.messages.len() > 0 {
-                msg.push_str(&matches.to_string());
+            if !matches.messages.is_empty() {
+                msg.push_str(&matches.output(args.output));
             }
         }
     } else {
         for matches in matches.iter() {
-            if matches.messages.len() > 0 {
-                msg.push_str(&matches.to_string());
+            if !matches.messages.is_empty() {
+                msg.push_str(&matches.output(args.output));
             }
         }
     }
 
     // Performance data
     msg.push_str(&format!(
-        "|critical={} warning={} lines={}",
-        criticals_count, warnings_count, lines_count
+        "|critical={} warning={} excluded={} lines={} unknown={}",
+        criticals_count, warnings_count, excluded_count, lines_count, unknowns_count
     ));
+    if !args.maintenance.is_empty() {
+        let suppressed_count = matches
+            .iter()
+            .fold(0, |count, matchh| count + matchh.suppressed_count);
+        msg.push_str(&format!(" suppressed={}", suppressed_count));
+    }
+    if args.learn.is_some() {
+        let known_count = matches
+            .iter()
+            .fold(0, |count, matchh| count + matchh.known_count);
+        msg.push_str(&format!(" known={}", known_count));
+    }
+    let mut pattern_names: Vec<&str> = vec![];
+    let patterns = args.groups.iter().flat_map(|group| &args.group_rules(group).patterns.patterns);
+    for name in patterns.filter_map(|pattern| pattern.name.as_deref()) {
+        if !pattern_names.contains(&name) {
+            pattern_names.push(name);
+        }
+    }
+    for name in pattern_names {
+        let count = pattern_counts.get(name).copied().unwrap_or_default();
+        msg.push_str(&format!(" {}={}", perfdata_label(name), count));
+    }
+    for (name, stats) in value_stats.iter() {
+        for stat in STAT_NAMES.iter() {
+            let thresholds = args
+                .stat_thresholds
+                .iter()
+                .find(|threshold| threshold.0 == *stat && threshold.2 == *name)
+                .map(|threshold| threshold.1.perfdata())
+                .unwrap_or_default();
+            msg.push_str(&format!(
+                " {}={}{}",
+                perfdata_label(&format!("{}_{}", name, stat)),
+                stats.get(stat).map_or(String::from("U"), |value| value.to_string()),
+                thresholds
+            ));
+        }
+    }
+    for (pattern, last_seen, _) in expect_results.iter() {
+        msg.push_str(&format!(
+            " {}={}s",
+            perfdata_label(&format!("{}_age", pattern)),
+            (now - *last_seen).num_seconds()
+        ));
+    }
+    for (file, modified, _) in age_results.iter() {
+        msg.push_str(&format!(
+            " {}={}s{}",
+            perfdata_label(&format!("{}_age", file[0].to_string_lossy())),
+            file_age(now, *modified),
+            args.max_age(file).map(|thresholds| thresholds.perfdata()).unwrap_or_default()
+        ));
+    }
+    for ((pattern, count, _), count_pattern) in count_results.iter().zip(&args.rules.patterns.count_patterns) {
+        msg.push_str(&format!(
+            " {}={}{}",
+            perfdata_label(pattern),
+            count,
+            count_pattern.0.perfdata()
+        ));
+    }
 
     // Print output message and exit
     println!("{}", msg.trim());
     exit(code as i32);
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_drop_messages() {
+        // given
+        let now = Utc::now();
+        let mut state = State::new(std::path::PathBuf::from("app.log"), None);
+        let new_match = || {
+            let mut matchh: Match = serde_json::from_str(
+                r#"{"path": "app.log", "lines_count": 1, "last_line_number": 1, "file_size": 10,
+                "messages": [], "keep_until": "2020-01-01T00:00:00Z"}"#,
+            )
+            .unwrap();
+            matchh.push_message(Message {
+                fingerprint: String::from("47773f6b3f8a08f4"),
+                message_type: ProblemType::WARNING,
+                message: String::from("Connection refused"),
+                ..Message::new()
+            });
+            matchh
+        };
+
+        // when
+        let mut suppressed = new_match();
+        drop_messages(&mut suppressed, &mut state, Some(ProblemType::CRITICAL), now, |_| true);
+        let mut found = new_match();
+        drop_messages(&mut found, &mut state, Some(ProblemType::CRITICAL), now, |_| false);
+
+        // then
+        assert!(suppressed.messages.is_empty());
+        assert_eq!(suppressed.suppressed_count, 1);
+        assert_eq!(suppressed.known_count, 0);
+        assert_eq!(found.messages.len(), 1);
+        assert_eq!(found.messages[0].message_type, ProblemType::CRITICAL);
+        assert_eq!(found.known_count, 0);
+        assert_eq!(state.learned.len(), 1);
+    }
+
+    #[test]
+    fn test_file_age() {
+        let now = Utc::now();
+        assert_eq!(file_age(now, now - Duration::seconds(90)), 90);
+        assert_eq!(file_age(now, now + Duration::seconds(90)), 0);
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Suppress problems during scheduled maintenance windows.
+
+use chrono::prelude::*;
+use chrono_tz::Tz;
+
+/// A weekly recurring time range in which problems are counted but do not raise the state.
+#[derive(Debug, PartialEq)]
+pub struct MaintenanceWindow {
+    /// The weekdays the window starts on, indexed from Monday.
+    days: [bool; 7],
+
+    /// The start time of the window.
+    start: NaiveTime,
+
+    /// The end time of the window. An end before the start ends the window on the next day.
+    end: NaiveTime,
+
+    /// The timezone of the times. The local timezone is used without timezone.
+    timezone: Option<Tz>,
+
+    /// The pattern or log file the window applies to.
+    /// A window without target applies to all patterns and log files.
+    pub target: Option<MaintenanceTarget>,
+}
+
+/// The pattern or log file a maintenance window applies to.
+#[derive(Debug, PartialEq)]
+pub enum MaintenanceTarget {
+    /// The name of a pattern given as 'pattern:NAME'.
+    Pattern(String),
+
+    /// The path of a log file given as 'file:PATH'.
+    File(String),
+}
+
+impl MaintenanceWindow {
+    /// Parse a window given as '[DAYS] HH:MM-HH:MM [TIMEZONE][,TARGET]'.
+    /// Days are weekdays like 'Sat' or ranges like 'Mon-Fri' separated by spaces. The window is
+    /// active every day without days. The target is a pattern as 'pattern:NAME' or a log file as
+    /// 'file:PATH'.
+    /// # Arguments
+    /// * `value` - The window string
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = |e: &str| {
+            format!(
+                "Invalid maintenance window '{}': {}. Use '[DAYS] HH:MM-HH:MM [TIMEZONE][,TARGET]'.",
+                value, e
+            )
+        };
+        let parts: Vec<&str> = value.splitn(2, ',').collect();
+        let mut days = [false; 7];
+        let mut times: Option<(NaiveTime, NaiveTime)> = None;
+        let mut timezone: Option<Tz> = None;
+        for token in parts[0].split_whitespace() {
+            if times.is_none() && token.contains(':') {
+                let range: Vec<&str> = token.splitn(2, '-').collect();
+                let time = |value: &str| {
+                    NaiveTime::parse_from_str(value, "%H:%M")
+                        .map_err(|_| invalid(&format!("invalid time '{}'", value)))
+                };
+                if range.len() < 2 {
+                    return Err(invalid("time range without end"));
+                }
+                times = Some((time(range[0])?, time(range[1])?));
+            } else if times.is_none() {
+                let range: Vec<&str> = token.splitn(2, '-').collect();
+                let day = |value: &str| {
+                    value
+                        .parse::<Weekday>()
+                        .map(|day| day.num_days_from_monday() as usize)
+                        .map_err(|_| invalid(&format!("invalid weekday '{}'", value)))
+                };
+                let first = day(range[0])?;
+                let last = match range.get(1) {
+                    Some(value) => day(value)?,
+                    None => first,
+                };
+                let mut index = first;
+                days[index] = true;
+                while index != last {
+                    index = (index + 1) % 7;
+                    days[index] = true;
+                }
+            } else if timezone.is_none() {
+                timezone = Some(
+                    token
+                        .parse::<Tz>()
+                        .map_err(|_| invalid(&format!("unknown timezone '{}'", token)))?,
+                );
+            } else {
+                return Err(invalid(&format!("unexpected '{}'", token)));
+            }
+        }
+        let (start, end) = times.ok_or_else(|| invalid("no time range"))?;
+        if !days.contains(&true) {
+            days = [true; 7];
+        }
+        let target = match parts.get(1) {
+            Some(target) => match target.split_once(':') {
+                Some(("pattern", name)) => Some(MaintenanceTarget::Pattern(name.to_string())),
+                Some(("file", path)) => Some(MaintenanceTarget::File(path.to_string())),
+                _ => return Err(invalid("target without prefix 'pattern:' or 'file:'")),
+            },
+            None => None,
+        };
+        Ok(MaintenanceWindow {
+            days,
+            start,
+            end,
+            timezone,
+            target,
+        })
+    }
+
+    /// Tests if the window is active at a date.
+    /// # Arguments
+    /// * `date` - The date to test
+    pub fn is_active(&self, date: DateTime<Utc>) -> bool {
+        let (day, time) = match self.timezone {
+            Some(timezone) => {
+                let date = date.with_timezone(&timezone);
+                (date.weekday(), date.time())
+            }
+            None => {
+                let date = date.with_timezone(&Local);
+                (date.weekday(), date.time())
+            }
+        };
+        let is_day = |day: Weekday| self.days[day.num_days_from_monday() as usize];
+        if self.start < self.end {
+            is_day(day) && time >= self.start && time < self.end
+        } else {
+            (is_day(day) && time >= self.start) || (is_day(day.pred()) && time < self.end)
+        }
+    }
+
+    /// Tests if the window applies to a pattern.
+    /// # Arguments
+    /// * `label` - The name of the pattern or its text if it has no name
+    pub fn applies_to_pattern(&self, label: &str) -> bool {
+        match &self.target {
+            Some(MaintenanceTarget::Pattern(name)) => name == label,
+            Some(MaintenanceTarget::File(_)) => false,
+            None => true,
+        }
+    }
+
+    /// Tests if the window applies to a log file.
+    /// # Arguments
+    /// * `path` - The path of the log file
+    pub fn applies_to_file(&self, path: &str) -> bool {
+        match &self.target {
+            Some(MaintenanceTarget::File(own)) => own == path,
+            Some(MaintenanceTarget::Pattern(_)) => false,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let date = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc);
+
+        // Friday 22:00 until Saturday 02:00 in Berlin, which is UTC+2 in summer
+        let window = MaintenanceWindow::parse("Mon-Fri 22:00-02:00 Europe/Berlin,pattern:batch").unwrap();
+        assert_eq!(window.target, Some(MaintenanceTarget::Pattern(String::from("batch"))));
+        assert!(window.is_active(date("2020-07-03T20:30:00Z")));
+        assert!(window.is_active(date("2020-07-03T23:59:00Z")));
+        assert!(!window.is_active(date("2020-07-04T00:00:00Z")));
+        assert!(!window.is_active(date("2020-07-04T20:30:00Z")));
+        assert!(window.applies_to_pattern("batch"));
+        assert!(!window.applies_to_pattern("other"));
+        assert!(!window.applies_to_file("batch"));
+
+        let window = MaintenanceWindow::parse("22:00-23:00,file:/var/log/batch.log").unwrap();
+        assert!(window.applies_to_file("/var/log/batch.log"));
+        assert!(!window.applies_to_pattern("/var/log/batch.log"));
+
+        let window = MaintenanceWindow::parse("Sat Sun 00:00-06:00 UTC").unwrap();
+        assert!(window.is_active(date("2020-07-05T05:59:00Z")));
+        assert!(!window.is_active(date("2020-07-06T05:59:00Z")));
+        assert!(window.applies_to_pattern("other"));
+        assert!(window.applies_to_file("other.log"));
+
+        assert!(MaintenanceWindow::parse("Mon-Fri").is_err());
+        assert!(MaintenanceWindow::parse("Xyz 22:00-23:00").is_err());
+        assert!(MaintenanceWindow::parse("22:00-23:00 Mars/Base").is_err());
+        assert!(MaintenanceWindow::parse("22:00-23:00,batch").is_err());
+    }
+}
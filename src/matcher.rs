@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Match many patterns against a message at once.
+
+use crate::logfile::{Pattern, Scope};
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use regex::RegexSet;
+
+/// Finds all patterns of a list matching a text.
+/// Patterns are grouped by the part of the message they apply to.
+pub struct Matcher {
+    /// The count of patterns.
+    len: usize,
+
+    /// Patterns applied to the whole message.
+    message: PatternSet,
+
+    /// Patterns applied to the first line of the message.
+    first_line: Option<PatternSet>,
+
+    /// Patterns applied to each line of the message.
+    lines: Option<PatternSet>,
+}
+
+/// Patterns applied to the same text.
+/// Fixed string patterns are searched with Aho-Corasick automatons and regular expressions with a
+/// regex set, so the text is scanned once per kind of pattern.
+struct PatternSet {
+    /// Automaton for case sensitive fixed strings and the indices of its patterns.
+    literals: Option<(AhoCorasick, Vec<usize>)>,
+
+    /// Automaton for case insensitive fixed strings and the indices of its patterns.
+    literals_nocase: Option<(AhoCorasick, Vec<usize>)>,
+
+    /// Regular expressions and the indices of its patterns.
+    regexes: (RegexSet, Vec<usize>),
+}
+
+impl Matcher {
+    /// Create a matcher for a list of patterns.
+    /// # Arguments
+    /// * `patterns` - The patterns to search for
+    pub fn new(patterns: &[&Pattern]) -> Result<Self, String> {
+        let scoped = |scope: Scope| -> Vec<(&Pattern, usize)> {
+            patterns
+                .iter()
+                .enumerate()
+                .filter(|(_, pattern)| pattern.scope == scope)
+                .map(|(index, pattern)| (*pattern, index))
+                .collect()
+        };
+        let optional = |patterns: Vec<(&Pattern, usize)>| match patterns.is_empty() {
+            true => Ok(None),
+            false => PatternSet::new(&patterns).map(Some),
+        };
+        Ok(Matcher {
+            len: patterns.len(),
+            message: PatternSet::new(&scoped(Scope::Message))?,
+            first_line: optional(scoped(Scope::FirstLine))?,
+            lines: optional(scoped(Scope::Lines))?,
+        })
+    }
+
+    /// Tests which patterns match the text. The result contains a flag for each pattern.
+    /// # Arguments
+    /// * `text` - The text to search through
+    pub fn matches(&self, text: &str) -> Vec<bool> {
+        let mut matched = vec![false; self.len];
+        self.message.matches(text, &mut matched);
+        if let Some(first_line) = &self.first_line {
+            first_line.matches(text.lines().next().unwrap_or_default(), &mut matched);
+        }
+        if let Some(lines) = &self.lines {
+            for line in text.lines() {
+                lines.matches(line, &mut matched);
+            }
+        }
+        matched
+    }
+}
+
+impl PatternSet {
+    /// Create a pattern set.
+    /// # Arguments
+    /// * `patterns` - The patterns and their indices in the matcher
+    fn new(patterns: &[(&Pattern, usize)]) -> Result<Self, String> {
+        let mut literals: Vec<(&str, usize)> = vec![];
+        let mut literals_nocase: Vec<(&str, usize)> = vec![];
+        let mut regexes: Vec<(&str, usize)> = vec![];
+        for (pattern, index) in patterns.iter() {
+            match (&pattern.literal, pattern.case_insensitive) {
+                (Some(literal), false) => literals.push((literal, *index)),
+                (Some(literal), true) => literals_nocase.push((literal, *index)),
+                (None, _) => regexes.push((pattern.re.as_str(), *index)),
+            }
+        }
+        let regex_set = RegexSet::new(regexes.iter().map(|regex| regex.0))
+            .map_err(|e| format!("Could not combine patterns: {}", e))?;
+        Ok(PatternSet {
+            literals: Self::build_automaton(&literals, false),
+            literals_nocase: Self::build_automaton(&literals_nocase, true),
+            regexes: (regex_set, regexes.iter().map(|regex| regex.1).collect()),
+        })
+    }
+
+    /// Build an automaton for fixed strings.
+    /// # Arguments
+    /// * `literals` - The fixed strings and the indices of its patterns
+    /// * `case_insensitive` - Match ASCII letters case insensitive
+    fn build_automaton(
+        literals: &[(&str, usize)],
+        case_insensitive: bool,
+    ) -> Option<(AhoCorasick, Vec<usize>)> {
+        if literals.is_empty() {
+            return None;
+        }
+        let automaton = AhoCorasickBuilder::new()
+            .ascii_case_insensitive(case_insensitive)
+            .build(literals.iter().map(|literal| literal.0));
+        Some((automaton, literals.iter().map(|literal| literal.1).collect()))
+    }
+
+    /// Set the flags of the patterns matching the text.
+    /// # Arguments
+    /// * `text` - The text to search through
+    /// * `matched` - The flags of all patterns of the matcher
+    fn matches(&self, text: &str, matched: &mut [bool]) {
+        for (automaton, indices) in self.literals.iter().chain(self.literals_nocase.iter()) {
+            for found in automaton.find_overlapping_iter(text) {
+                matched[indices[found.pattern()]] = true;
+            }
+        }
+        for index in self.regexes.0.matches(text).iter() {
+            matched[self.regexes.1[index]] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::logfile::ProblemType;
+
+    #[test]
+    fn test_matches() {
+        // given
+        let patterns: Vec<Pattern> = vec![
+            r"OutOfMemoryError",
+            r"/i=timeout",
+            r"Exception: \w+",
+            r"/l=a.b",
+            r"missing",
+            r"/im=^exception: A",
+            r"/f=^java.*Read",
+            r"/f=^Exception",
+            r"/e=^Exception: a\.b$",
+        ]
+        .into_iter()
+        .map(|pattern| Pattern::parse(pattern, ProblemType::CRITICAL).unwrap())
+        .collect();
+        let matcher = Matcher::new(&patterns.iter().collect::<Vec<_>>()).unwrap();
+
+        // when
+        let matched = matcher.matches("java.lang.OutOfMemoryError: Read TIMEOUT\nException: a.b");
+
+        // then
+        assert!(patterns[0].literal.is_some());
+        assert!(patterns[2].literal.is_none());
+        assert_eq!(matched, vec![true, true, true, true, false, true, true, false, true]);
+        assert_eq!(matcher.matches("axb"), vec![false; 9]);
+    }
+}
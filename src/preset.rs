@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Built-in line patterns detecting message starts and continuation lines of common log formats.
+
+/// Lines continuing a Java message: stack frames, causes and exception lines.
+const JAVA_CONTINUATION: &str =
+    r"^(\s|Caused by: |Suppressed: |[\w$.]+(Exception|Error|Throwable)(: |$)|\.\.\. \d+ (more|common frames omitted))";
+
+/// Tuples of preset names, line patterns matching the first line of a message and continuation
+/// patterns matching the other lines of a message. Lines matching neither pattern start a new
+/// message. Without continuation pattern any line not matching the line pattern continues the
+/// previous message.
+pub const LINE_PRESETS: [(&str, &str, Option<&str>); 7] = [
+    // 2020-05-01 12:00:01,123 [main] ERROR com.example.App - text or ERROR [main] text
+    (
+        "log4j",
+        r"^(\d{4}-\d\d-\d\d[ T]\d\d:\d\d:\d\d[,.]\d{3} |\[?(TRACE|DEBUG|INFO|WARN|ERROR|FATAL)\]? )",
+        Some(JAVA_CONTINUATION),
+    ),
+    // 12:00:01.123 [main] ERROR com.example.App - text or the same with a date like Spring Boot
+    ("logback", r"^(\d{4}-\d\d-\d\d[ T])?\d\d:\d\d:\d\d\.\d{3}", Some(JAVA_CONTINUATION)),
+    // 2020-05-01 12:00:01,123 - app - ERROR - text or ERROR:app:text but not ValueError: text
+    (
+        "python",
+        r"^(\d{4}-\d\d-\d\d \d\d:\d\d:\d\d,\d{3} |(DEBUG|INFO|WARNING|ERROR|CRITICAL):)",
+        // Traceback lines, blank lines and the exception line ending a traceback
+        Some(concat!(
+            r"^(\s|$|Traceback \(most recent call last\):|During handling of the above exception|",
+            r"The above exception was the direct cause|[\w.]+(Error|Exception|Warning|Exit|Interrupt|Iteration)(: |$))"
+        )),
+    ),
+    // May  1 12:00:01 host app[123]: text or 2020-05-01T12:00:01.123456+02:00 host app[123]: text
+    (
+        "syslog",
+        r"^([A-Z][a-z]{2} [ \d]\d \d\d:\d\d:\d\d|\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d(\.\d+)?(Z|[+-]\d\d:\d\d)) ",
+        Some(r"^\s"),
+    ),
+    // 2020/05/01 12:00:01 [error] 123#0: *1 text
+    ("nginx-error", r"^\d{4}/\d\d/\d\d \d\d:\d\d:\d\d \[[a-z]+\] ", Some(r"^\s")),
+    // [5/1/20 12:00:01:123 CEST] 0000002a SystemOut     O text
+    ("websphere", r"^\[[^\]]+\] [\da-f]{8} ", Some(JAVA_CONTINUATION)),
+    // 2020-05-01T12:00:01.123Z text or [2020-05-01 12:00:01] text
+    // Used by many formats, so lines not matching continue the previous message
+    ("iso8601", r"^\[?\d{4}-\d\d-\d\d[T ]\d\d:\d\d:\d\d", None),
+];
+
+/// Get the line pattern of a preset.
+/// # Arguments
+/// * `name` - The name of the preset
+pub fn line_pattern(name: &str) -> Option<&'static str> {
+    LINE_PRESETS
+        .iter()
+        .find(|preset| preset.0 == name)
+        .map(|preset| preset.1)
+}
+
+/// Get the continuation pattern of a preset.
+/// # Arguments
+/// * `name` - The name of the preset
+pub fn continuation_pattern(name: &str) -> Option<&'static str> {
+    LINE_PRESETS
+        .iter()
+        .find(|preset| preset.0 == name)
+        .and_then(|preset| preset.2)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use regex::Regex;
+
+    /// Get the indexes of the lines starting a message.
+    fn starts(name: &str, text: &str) -> Vec<usize> {
+        let re = Regex::new(line_pattern(name).unwrap()).unwrap();
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| re.is_match(line))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Get the indexes of the lines starting a message including lines not continuing a message.
+    fn message_starts(name: &str, text: &str) -> Vec<usize> {
+        let re = Regex::new(line_pattern(name).unwrap()).unwrap();
+        let continuation_re = continuation_pattern(name).map(|pattern| Regex::new(pattern).unwrap());
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| re.is_match(line) || continuation_re.as_ref().is_some_and(|re| !re.is_match(line)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    #[test]
+    fn test_line_presets() {
+        // given
+        let logs = [
+            (
+                "log4j",
+                "2020-05-01 12:00:01,123 [main] INFO  com.example.App - Started\n\
+                 2020-05-01 12:00:02,456 [main] ERROR com.example.App - Failed\n\
+                 java.lang.IllegalStateException: broken\n\
+                 \tat com.example.App.main(App.java:10)\n\
+                 ERROR [worker-1] com.example.Job - Failed\n",
+                vec![0, 1, 4],
+            ),
+            (
+                "logback",
+                "12:00:01.123 [main] INFO  com.example.App - Started\n\
+                 2020-05-01 12:00:02.456  ERROR 1234 --- [main] com.example.App : Failed\n\
+                 java.lang.IllegalStateException: broken\n\
+                 \tat com.example.App.main(App.java:10)\n\
+                 2020-05-01T12:00:03.789+02:00  INFO 1234 --- [main] com.example.App : Done\n",
+                vec![0, 1, 4],
+            ),
+            (
+                "python",
+                "2020-05-01 12:00:01,123 - app - ERROR - Failed\n\
+                 Traceback (most recent call last):\n\
+                 \x20 File \"app.py\", line 10, in <module>\n\
+                 ValueError: broken\n\
+                 WARNING:app:Retrying\n",
+                vec![0, 4],
+            ),
+            (
+                "syslog",
+                "May  1 12:00:01 host app[123]: Failed\n\
+                 \x20   continued text\n\
+                 May 11 12:00:02 host app[123]: Done\n\
+                 2020-05-01T12:00:03.123456+02:00 host app[123]: Done\n",
+                vec![0, 2, 3],
+            ),
+            (
+                "nginx-error",
+                "2020/05/01 12:00:01 [error] 123#0: *1 upstream timed out\n\
+                 2020/05/01 12:00:02 [crit] 123#0: *2 open() failed\n\
+                 \x20 while reading response header\n",
+                vec![0, 1],
+            ),
+            (
+                "websphere",
+                "[5/1/20 12:00:01:123 CEST] 0000002a SystemOut     O Started\n\
+                 [5/1/20 12:00:02:456 CEST] 0000002b SystemErr     R java.lang.NullPointerException\n\
+                 [5/1/20 12:00:02:456 CEST] 0000002b SystemErr     R \tat com.example.App.main(App.java:10)\n\
+                 \tat com.example.App.run(App.java:20)\n\
+                 [1] not a start\n",
+                vec![0, 1, 2],
+            ),
+            (
+                "iso8601",
+                "2020-05-01T12:00:01.123Z Started\n\
+                 [2020-05-01 12:00:02] Failed\n\
+                 \x20 details\n",
+                vec![0, 1],
+            ),
+        ];
+
+        for (name, text, expected) in logs.iter() {
+            // when
+            let result = starts(name, text);
+
+            // then
+            assert_eq!(&result, expected, "preset {}", name);
+        }
+        assert_eq!(logs.len(), LINE_PRESETS.len());
+        assert_eq!(line_pattern("unknown"), None);
+    }
+
+    #[test]
+    fn test_continuation_patterns() {
+        // given
+        let logs = [
+            (
+                "log4j",
+                "2020-05-01 12:00:02,456 [main] ERROR com.example.App - Failed\n\
+                 java.lang.IllegalStateException: broken\n\
+                 \tat com.example.App.main(App.java:10)\n\
+                 Caused by: java.io.IOException\n\
+                 \t... 3 more\n\
+                 Hello from System.out\n",
+                vec![0, 5],
+            ),
+            (
+                "python",
+                "2020-05-01 12:00:01,123 - app - ERROR - Failed\n\
+                 Traceback (most recent call last):\n\
+                 \x20 File \"app.py\", line 10, in <module>\n\
+                 ValueError: broken\n\
+                 \n\
+                 During handling of the above exception, another exception occurred:\n\
+                 print output\n",
+                vec![0, 6],
+            ),
+            ("syslog", "May  1 12:00:01 host app[123]: Failed\n\x20   continued text\nstray text\n", vec![0, 2]),
+            ("iso8601", "2020-05-01T12:00:01.123Z Started\nany text\n", vec![0]),
+        ];
+
+        for (name, text, expected) in logs.iter() {
+            // when
+            let result = message_starts(name, text);
+
+            // then
+            assert_eq!(&result, expected, "preset {}", name);
+        }
+        for preset in LINE_PRESETS.iter() {
+            assert!(preset.2.map_or(true, |pattern| Regex::new(pattern).is_ok()), "preset {}", preset.0);
+        }
+    }
+}
@@ -4,7 +4,8 @@
 
 //! Load and save log file states.
 
-use crate::logfile::Match;
+use crate::logfile::{Match, Message, PendingCorrelation};
+use chrono::prelude::*;
 use fs2::FileExt;
 use serde::{Deserialize, Serialize};
 use std::fs::create_dir_all;
@@ -13,12 +14,22 @@ use std::io::{prelude::*, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+/// The maximum number of captured values kept per value pattern. The oldest values are dropped first.
+pub const MAX_PATTERN_VALUES: usize = 10_000;
+
+/// The maximum number of messages learned per log file. The least recently seen messages are forgotten first.
+pub const MAX_LEARNED_MESSAGES: usize = 10_000;
+
 /// Holds the state informations about a log file.
 #[derive(Serialize, Deserialize)]
 pub struct State {
     /// Path to the log file.
     pub path: PathBuf,
 
+    /// Name of the check from the configuration file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check: Option<String>,
+
     /// Size of the log file.
     #[serde(default)]
     pub size: u64,
@@ -34,21 +45,279 @@ pub struct State {
     /// Kept messages from previous runs
     #[serde(default)]
     pub kept_matches: Vec<Match>,
+
+    /// Match counts of rate patterns from previous runs
+    #[serde(default)]
+    pub pattern_hits: Vec<PatternHits>,
+
+    /// Captured values of value patterns from previous runs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pattern_values: Vec<PatternValues>,
+
+    /// Last matches of expect patterns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pattern_seen: Vec<PatternSeen>,
+
+    /// Correlations waiting for their end
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_correlations: Vec<PendingCorrelation>,
+
+    /// Messages learned in learning mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub learned: Vec<LearnedMessage>,
+}
+
+/// Holds the timestamped match counts of a rate pattern.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PatternHits {
+    /// The pattern the matches are counted for.
+    pub pattern: String,
+
+    /// The time window of the rate pattern in seconds. Unknown for states of previous versions.
+    #[serde(default)]
+    pub window: i64,
+
+    /// The match counts per run.
+    pub hits: Vec<(DateTime<Utc>, usize)>,
+}
+
+/// Holds the timestamped captured values of a value pattern.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PatternValues {
+    /// The pattern the values are captured by.
+    pub pattern: String,
+
+    /// The captured values.
+    pub values: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// Holds the date of the last match of an expect pattern.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PatternSeen {
+    /// The pattern expected to match.
+    pub pattern: String,
+
+    /// The date of the run the pattern matched last or the first run watching for the pattern.
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Holds a message learned in learning mode.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LearnedMessage {
+    /// The fingerprint of the message.
+    pub fingerprint: String,
+
+    /// The date of the run the message has been learned in.
+    pub first_seen: DateTime<Utc>,
+
+    /// The date of the last run the message has been found in.
+    pub last_seen: DateTime<Utc>,
+
+    /// The count of messages found with this fingerprint.
+    pub count: usize,
+
+    /// The first line of the first message found.
+    pub message: String,
 }
 
 impl State {
     /// Create a new default log file state.
-    pub fn new(log_file: PathBuf) -> Self {
+    /// # Arguments
+    /// * `log_file` - Path to the log file
+    /// * `check` - Name of the check from the configuration file
+    pub fn new(log_file: PathBuf, check: Option<String>) -> Self {
         State {
             path: log_file,
+            check,
             size: 0,
             modified: SystemTime::UNIX_EPOCH,
             line_number: -1,
             kept_matches: vec![],
+            pattern_hits: vec![],
+            pattern_values: vec![],
+            pattern_seen: vec![],
+            pending_correlations: vec![],
+            learned: vec![],
+        }
+    }
+
+    /// Get the match counts of a rate pattern.
+    /// Rate patterns with the same pattern but different time windows count separately.
+    /// # Arguments
+    /// * `pattern` - The pattern the matches are counted for
+    /// * `window` - The time window of the rate pattern in seconds
+    pub fn pattern_hits_mut(&mut self, pattern: &str, window: i64) -> &mut PatternHits {
+        match self
+            .pattern_hits
+            .iter()
+            .position(|hits| hits.is_for(pattern, window))
+        {
+            Some(index) => &mut self.pattern_hits[index],
+            None => {
+                self.pattern_hits.push(PatternHits {
+                    pattern: pattern.to_string(),
+                    window,
+                    hits: vec![],
+                });
+                self.pattern_hits.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Drop match counts older than a date and rate patterns without match counts left.
+    /// # Arguments
+    /// * `since` - The start date of the largest time window of all rate patterns
+    pub fn prune_hits(&mut self, since: DateTime<Utc>) {
+        for hits in self.pattern_hits.iter_mut() {
+            hits.hits.retain(|hit| hit.0 >= since);
+        }
+        self.pattern_hits.retain(|hits| !hits.hits.is_empty());
+    }
+
+    /// Drop captured values older than a date and value patterns without values left.
+    /// # Arguments
+    /// * `since` - The start date of the value window
+    pub fn prune_values(&mut self, since: DateTime<Utc>) {
+        for values in self.pattern_values.iter_mut() {
+            values.values.retain(|value| value.0 >= since);
+        }
+        self.pattern_values.retain(|values| !values.values.is_empty());
+    }
+
+    /// Remember captured values of a value pattern. Only the latest `MAX_PATTERN_VALUES` values are kept.
+    /// # Arguments
+    /// * `pattern` - The pattern the values are captured by
+    /// * `now` - The date of the current run
+    /// * `values` - The captured values
+    pub fn add_values(&mut self, pattern: &str, now: DateTime<Utc>, values: &[f64]) {
+        let pattern_values = &mut self.pattern_values_mut(pattern).values;
+        pattern_values.extend(values.iter().map(|value| (now, *value)));
+        if pattern_values.len() > MAX_PATTERN_VALUES {
+            pattern_values.drain(..pattern_values.len() - MAX_PATTERN_VALUES);
+        }
+    }
+
+    /// Get the captured values of a value pattern.
+    /// # Arguments
+    /// * `pattern` - The pattern the values are captured by
+    pub fn pattern_values_mut(&mut self, pattern: &str) -> &mut PatternValues {
+        match self.pattern_values.iter().position(|values| values.pattern == pattern) {
+            Some(index) => &mut self.pattern_values[index],
+            None => {
+                self.pattern_values.push(PatternValues {
+                    pattern: pattern.to_string(),
+                    values: vec![],
+                });
+                self.pattern_values.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Learn the fingerprint of a message or count it if it is learned already.
+    /// Only `MAX_LEARNED_MESSAGES` messages are kept, learned messages can be forgotten by `forget` too.
+    /// Returns true if the fingerprint has not been learned before.
+    /// # Arguments
+    /// * `message` - The message to learn
+    /// * `now` - The date of the current run
+    pub fn learn(&mut self, message: &Message, now: DateTime<Utc>) -> bool {
+        match self
+            .learned
+            .iter_mut()
+            .find(|learned| learned.fingerprint == message.fingerprint)
+        {
+            Some(learned) => {
+                learned.last_seen = now;
+                learned.count += message.count;
+                false
+            }
+            None => {
+                if self.learned.len() >= MAX_LEARNED_MESSAGES {
+                    if let Some(index) = (0..self.learned.len()).min_by_key(|index| self.learned[*index].last_seen) {
+                        self.learned.remove(index);
+                    }
+                }
+                self.learned.push(LearnedMessage {
+                    fingerprint: message.fingerprint.clone(),
+                    first_seen: now,
+                    last_seen: now,
+                    count: message.count,
+                    message: message.message.lines().next().unwrap_or_default().to_string(),
+                });
+                true
+            }
+        }
+    }
+
+    /// Forget learned messages by fingerprint. All learned messages are forgotten without fingerprints.
+    /// Returns the count of forgotten messages.
+    /// # Arguments
+    /// * `fingerprints` - The fingerprints of the messages to forget
+    pub fn forget(&mut self, fingerprints: &[String]) -> usize {
+        let count = self.learned.len();
+        self.learned
+            .retain(|learned| !fingerprints.is_empty() && !fingerprints.contains(&learned.fingerprint));
+        count - self.learned.len()
+    }
+
+    /// Acknowledge kept messages to remove them. All kept messages are acknowledged without
+    /// fingerprints and date. Returns the count of acknowledged messages.
+    /// # Arguments
+    /// * `fingerprints` - The fingerprints of the messages to acknowledge
+    /// * `before` - Acknowledge the messages found before this date only
+    pub fn ack(&mut self, fingerprints: &[String], before: Option<DateTime<Utc>>) -> usize {
+        let mut count = 0;
+        for kept_match in self.kept_matches.iter_mut() {
+            // Kept messages of previous versions without date are found before any date
+            let is_before = before.map_or(true, |before| kept_match.checked.map_or(true, |checked| checked < before));
+            let kept_count = kept_match.messages.len();
+            kept_match.messages.retain(|message| {
+                let is_selected = fingerprints.is_empty() || fingerprints.contains(&message.fingerprint);
+                !(is_before && is_selected)
+            });
+            count += kept_count - kept_match.messages.len();
+        }
+        self.kept_matches.retain(|kept_match| !kept_match.messages.is_empty());
+        count
+    }
+
+    /// Get the date of the last match of an expect pattern.
+    /// # Arguments
+    /// * `pattern` - The pattern expected to match
+    /// * `now` - The date used if the pattern is watched for the first time
+    pub fn pattern_seen_mut(&mut self, pattern: &str, now: DateTime<Utc>) -> &mut PatternSeen {
+        match self.pattern_seen.iter().position(|seen| seen.pattern == pattern) {
+            Some(index) => &mut self.pattern_seen[index],
+            None => {
+                self.pattern_seen.push(PatternSeen {
+                    pattern: pattern.to_string(),
+                    last_seen: now,
+                });
+                self.pattern_seen.last_mut().unwrap()
+            }
         }
     }
 }
 
+impl PatternHits {
+    /// Tests if the match counts belong to a rate pattern.
+    /// # Arguments
+    /// * `pattern` - The pattern the matches are counted for
+    /// * `window` - The time window of the rate pattern in seconds
+    pub fn is_for(&self, pattern: &str, window: i64) -> bool {
+        self.pattern == pattern && self.window == window
+    }
+
+    /// Sum up the match counts since a date.
+    /// # Arguments
+    /// * `since` - The start date of the time window
+    pub fn count_since(&self, since: DateTime<Utc>) -> usize {
+        self.hits
+            .iter()
+            .filter(|hit| hit.0 >= since)
+            .fold(0, |count, hit| count + hit.1)
+    }
+}
+
 /// A state document holding several log file states.
 #[derive(Serialize, Deserialize)]
 pub struct StateDoc {
@@ -94,7 +363,7 @@ impl StateLoader {
         let mut content = String::new();
         file.read_to_string(&mut content)
             .map_err(|e| format!("Could not read state file: {}", e))?;
-        if content.len() == 0 {
+        if content.is_empty() {
             return Ok(StateDoc::new());
         }
         match serde_json::from_str(&content) {
@@ -122,33 +391,31 @@ impl StateLoader {
 
     /// Open or get the state file handle.
     fn open_file(&mut self) -> Result<&mut File, String> {
-        match self.file.as_ref() {
-            None => {
-                let dir = self.path.parent()
-                    .ok_or(format!("No parent dir for state file '{}'", self.path.to_string_lossy()))?;
-                if !dir.exists() {
-                    create_dir_all(dir)
-                        .map_err(|e| format!("Could not create state file parent directory '{}': {}", self.path.to_string_lossy(), e))?;
-                }
-                let file = OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(self.path.as_path())
-                    .map_err(|e| format!("Could not open state file '{}': {}", self.path.to_string_lossy(), e))?;
-                file.lock_exclusive()
-                    .map_err(|e| format!("Could not lock state file: {}", e))?;
-                self.file = Some(file);
+        if self.file.is_none() {
+            let dir = self.path.parent()
+                .ok_or(format!("No parent dir for state file '{}'", self.path.to_string_lossy()))?;
+            if !dir.exists() {
+                create_dir_all(dir)
+                    .map_err(|e| format!("Could not create state file parent directory '{}': {}", self.path.to_string_lossy(), e))?;
             }
-            _ => (),
-        };
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(self.path.as_path())
+                .map_err(|e| format!("Could not open state file '{}': {}", self.path.to_string_lossy(), e))?;
+            file.lock_exclusive()
+                .map_err(|e| format!("Could not lock state file: {}", e))?;
+            self.file = Some(file);
+        }
         Ok(self.file.as_mut().unwrap())
     }
 
     /// Close state file handle.
     pub fn close_file(&mut self) -> Result<(), String> {
         let file = self.open_file()?;
-        match file.unlock() {
+        match FileExt::unlock(file) {
             Ok(()) => {
                 self.file = None;
                 Ok(())
@@ -157,3 +424,176 @@ impl StateLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_pattern_hits() {
+        // given
+        let now = Utc::now();
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        state.pattern_hits_mut("timeout", 600).hits.push((now - Duration::seconds(300), 2));
+        state.pattern_hits_mut("timeout", 600).hits.push((now, 1));
+        state.pattern_hits_mut("timeout", 60).hits.push((now - Duration::seconds(300), 5));
+        state.pattern_hits_mut("removed", 60).hits.push((now - Duration::seconds(900), 1));
+
+        // when
+        state.prune_hits(now - Duration::seconds(600));
+
+        // then
+        assert_eq!(state.pattern_hits.len(), 2);
+        assert_eq!(state.pattern_hits_mut("timeout", 600).count_since(now - Duration::seconds(600)), 3);
+        assert_eq!(state.pattern_hits_mut("timeout", 600).count_since(now - Duration::seconds(60)), 1);
+        assert_eq!(state.pattern_hits_mut("timeout", 60).count_since(now - Duration::seconds(60)), 0);
+    }
+
+    #[test]
+    fn test_pattern_values() {
+        // given
+        let now = Utc::now();
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        state.add_values("duration", now - Duration::seconds(900), &[1.0, 2.0]);
+        state.add_values("removed", now - Duration::seconds(900), &[3.0]);
+        state.add_values("duration", now, &[4.0]);
+
+        // when
+        state.prune_values(now - Duration::seconds(600));
+        let values: Vec<f64> = (0..MAX_PATTERN_VALUES).map(|value| value as f64).collect();
+        state.add_values("duration", now, &values);
+
+        // then
+        assert_eq!(state.pattern_values.len(), 1);
+        let values = &state.pattern_values_mut("duration").values;
+        assert_eq!(values.len(), MAX_PATTERN_VALUES);
+        assert_eq!(values[0].1, 0.0);
+        assert_eq!(values[MAX_PATTERN_VALUES - 1].1, (MAX_PATTERN_VALUES - 1) as f64);
+    }
+
+    #[test]
+    fn test_learn() {
+        // given
+        let now = Utc::now();
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        let message = |text: &str| Message {
+            count: 2,
+            fingerprint: crate::fingerprint::fingerprint(text),
+            message: text.to_string(),
+            ..Message::new()
+        };
+
+        // when
+        let is_new = state.learn(&message("Connection refused to 10.0.0.5\n\tat Foo.bar()\n"), now);
+        let is_repeated_new = state.learn(
+            &message("Connection refused to 10.0.0.6\n\tat Foo.bar()\n"),
+            now + Duration::seconds(60),
+        );
+
+        // then
+        assert!(is_new);
+        assert!(!is_repeated_new);
+        assert_eq!(state.learned.len(), 1);
+        assert_eq!(state.learned[0].count, 4);
+        assert_eq!(state.learned[0].first_seen, now);
+        assert_eq!(state.learned[0].last_seen, now + Duration::seconds(60));
+        assert_eq!(state.learned[0].message, "Connection refused to 10.0.0.5");
+    }
+
+    #[test]
+    fn test_learn_limit() {
+        // given
+        let now = Utc::now();
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        let message = |fingerprint: String| Message {
+            fingerprint,
+            ..Message::new()
+        };
+        for index in 0..MAX_LEARNED_MESSAGES {
+            state.learn(&message(index.to_string()), now + Duration::seconds(index as i64));
+        }
+        state.learn(&message(String::from("0")), now + Duration::seconds(MAX_LEARNED_MESSAGES as i64));
+
+        // when
+        let is_new = state.learn(&message(String::from("new")), now + Duration::seconds(MAX_LEARNED_MESSAGES as i64));
+
+        // then
+        assert!(is_new);
+        assert_eq!(state.learned.len(), MAX_LEARNED_MESSAGES);
+        assert!(state.learned.iter().any(|learned| learned.fingerprint == "0"));
+        assert!(!state.learned.iter().any(|learned| learned.fingerprint == "1"));
+    }
+
+    #[test]
+    fn test_forget() {
+        // given
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        for text in ["timeout", "refused", "closed"] {
+            state.learn(
+                &Message {
+                    fingerprint: text.to_string(),
+                    ..Message::new()
+                },
+                Utc::now(),
+            );
+        }
+
+        // when
+        let forgotten = state.forget(&[String::from("refused"), String::from("unknown")]);
+
+        // then
+        assert_eq!(forgotten, 1);
+        let fingerprints: Vec<&str> = state.learned.iter().map(|learned| learned.fingerprint.as_str()).collect();
+        assert_eq!(fingerprints, vec!["timeout", "closed"]);
+        assert_eq!(state.forget(&[]), 2);
+        assert!(state.learned.is_empty());
+    }
+
+    #[test]
+    fn test_ack() {
+        // given
+        let now = Utc::now();
+        let kept_match = |checked: Option<DateTime<Utc>>, fingerprints: &[&str]| {
+            let mut kept_match: Match = serde_json::from_str(
+                r#"{"path": "app.log", "lines_count": 0, "last_line_number": 0, "file_size": 0,
+                    "messages": [], "keep_until": "2100-01-01T00:00:00Z"}"#,
+            )
+            .unwrap();
+            kept_match.checked = checked;
+            kept_match.messages = fingerprints
+                .iter()
+                .map(|fingerprint| Message {
+                    fingerprint: fingerprint.to_string(),
+                    ..Message::new()
+                })
+                .collect();
+            kept_match
+        };
+        let mut state = State::new(PathBuf::from("app.log"), None);
+        state.kept_matches = vec![
+            kept_match(None, &["legacy"]),
+            kept_match(Some(now - Duration::hours(2)), &["timeout", "refused"]),
+            kept_match(Some(now), &["timeout"]),
+        ];
+        let fingerprints = |state: &State| -> Vec<Vec<String>> {
+            state
+                .kept_matches
+                .iter()
+                .map(|kept_match| kept_match.messages.iter().map(|message| message.fingerprint.clone()).collect())
+                .collect()
+        };
+
+        // when
+        let acknowledged = state.ack(&[String::from("timeout")], Some(now - Duration::hours(1)));
+
+        // then
+        assert_eq!(acknowledged, 1);
+        assert_eq!(fingerprints(&state), vec![vec!["legacy"], vec!["refused"], vec!["timeout"]]);
+        assert_eq!(state.ack(&[], Some(now - Duration::hours(1))), 2);
+        assert_eq!(fingerprints(&state), vec![vec!["timeout"]]);
+        assert_eq!(state.ack(&[], None), 1);
+        assert!(state.kept_matches.is_empty());
+    }
+}
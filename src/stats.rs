@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Calculate statistics of values captured from messages.
+
+use crate::threshold::Thresholds;
+
+/// The names of the available statistics.
+pub static STAT_NAMES: [&str; 8] = ["count", "min", "max", "avg", "sum", "p50", "p90", "p99"];
+
+/// A tuple containing the name of the statistic, its thresholds and the name of the value pattern.
+pub type StatThreshold = (String, Thresholds, String);
+
+/// Statistics of a list of values.
+pub struct Stats {
+    /// The values sorted ascending.
+    sorted: Vec<f64>,
+
+    /// The sum of the values.
+    sum: f64,
+}
+
+impl Stats {
+    /// Calculate the statistics of values.
+    /// # Arguments
+    /// * `values` - The values
+    pub fn new(values: &[f64]) -> Self {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Stats {
+            sum: sorted.iter().sum(),
+            sorted,
+        }
+    }
+
+    /// Get a statistic by its name. There is no value for empty lists except count and sum.
+    /// # Arguments
+    /// * `name` - The name of the statistic
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "count" => Some(self.sorted.len() as f64),
+            "sum" => Some(self.sum),
+            "min" => self.sorted.first().copied(),
+            "max" => self.sorted.last().copied(),
+            "avg" if !self.sorted.is_empty() => Some(self.sum / self.sorted.len() as f64),
+            "p50" => self.percentile(50.0),
+            "p90" => self.percentile(90.0),
+            "p99" => self.percentile(99.0),
+            _ => None,
+        }
+    }
+
+    /// Get a percentile by the nearest rank method.
+    /// # Arguments
+    /// * `percent` - The percentage of values less or equal to the result
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        if self.sorted.is_empty() {
+            return None;
+        }
+        let rank = (percent / 100.0 * self.sorted.len() as f64).ceil() as usize;
+        Some(self.sorted[rank.clamp(1, self.sorted.len()) - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let values: Vec<f64> = (1..=100).rev().map(|value| value as f64).collect();
+        let stats = Stats::new(&values);
+        assert_eq!(stats.get("count"), Some(100.0));
+        assert_eq!(stats.get("min"), Some(1.0));
+        assert_eq!(stats.get("max"), Some(100.0));
+        assert_eq!(stats.get("avg"), Some(50.5));
+        assert_eq!(stats.get("sum"), Some(5050.0));
+        assert_eq!(stats.get("p50"), Some(50.0));
+        assert_eq!(stats.get("p90"), Some(90.0));
+        assert_eq!(stats.get("p99"), Some(99.0));
+
+        let stats = Stats::new(&[]);
+        assert_eq!(stats.get("count"), Some(0.0));
+        assert_eq!(stats.get("avg"), None);
+        assert_eq!(stats.get("p99"), None);
+
+        let stats = Stats::new(&[2.0, f64::NAN, 1.0]);
+        assert_eq!(stats.get("min"), Some(1.0));
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright (c) 2020 Michael Richter <mr@osor.de>
+ */
+
+//! Parse and evaluate Nagios threshold ranges.
+
+use crate::logfile::ProblemType;
+use std::fmt::{Display, Formatter};
+
+/// A Nagios threshold range like `10`, `10:`, `~:10`, `10:20` or `@10:20`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    /// Lower bound of the range.
+    pub start: f64,
+
+    /// Upper bound of the range.
+    pub end: f64,
+
+    /// Alert if the value is inside the range instead of outside.
+    pub inside: bool,
+
+    /// The range as it was given.
+    raw: String,
+}
+
+/// A pair of optional WARNING and CRITICAL ranges.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    /// Range for WARNING state.
+    pub warning: Option<Range>,
+
+    /// Range for CRITICAL state.
+    pub critical: Option<Range>,
+}
+
+impl Range {
+    /// Parse a range in Nagios range syntax.
+    /// # Arguments
+    /// * `value` - The range string
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid threshold range '{}'", value);
+        let (inside, range) = match value.strip_prefix('@') {
+            Some(range) => (true, range),
+            None => (false, value),
+        };
+        let (start, end) = match range.find(':') {
+            Some(index) => (&range[..index], &range[index + 1..]),
+            None => ("0", range),
+        };
+        let start = match start {
+            "~" => f64::NEG_INFINITY,
+            "" => 0.0,
+            value => value.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" => f64::INFINITY,
+            value => value.parse().map_err(|_| invalid())?,
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Range {
+            start,
+            end,
+            inside,
+            raw: value.to_string(),
+        })
+    }
+
+    /// Tests if the value should raise an alert.
+    /// # Arguments
+    /// * `value` - The value to be tested
+    pub fn is_alert(&self, value: f64) -> bool {
+        let is_inside = value >= self.start && value <= self.end;
+        is_inside == self.inside
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Thresholds {
+    /// Parse WARNING and CRITICAL ranges. Empty strings mean no range.
+    /// # Arguments
+    /// * `warning` - The WARNING range string
+    /// * `critical` - The CRITICAL range string
+    pub fn parse(warning: &str, critical: &str) -> Result<Self, String> {
+        let parse = |value: &str| match value {
+            "" => Ok(None),
+            value => Range::parse(value).map(Some),
+        };
+        Ok(Thresholds {
+            warning: parse(warning)?,
+            critical: parse(critical)?,
+        })
+    }
+
+    /// Get the state for a value.
+    /// # Arguments
+    /// * `value` - The value to be tested
+    pub fn check(&self, value: f64) -> ProblemType {
+        let is_alert = |range: &Option<Range>| range.as_ref().is_some_and(|range| range.is_alert(value));
+        if is_alert(&self.critical) {
+            ProblemType::CRITICAL
+        } else if is_alert(&self.warning) {
+            ProblemType::WARNING
+        } else {
+            ProblemType::OK
+        }
+    }
+
+    /// Format the thresholds as `;warning;critical` for performance data. Without any range the
+    /// result is empty.
+    pub fn perfdata(&self) -> String {
+        if self.warning.is_none() && self.critical.is_none() {
+            return String::new();
+        }
+        let format = |range: &Option<Range>| range.as_ref().map(|range| range.to_string()).unwrap_or_default();
+        format!(";{};{}", format(&self.warning), format(&self.critical))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let range = Range::parse("10").unwrap();
+        assert!(range.is_alert(11.0));
+        assert!(range.is_alert(-1.0));
+        assert!(!range.is_alert(10.0));
+
+        let range = Range::parse("~:10").unwrap();
+        assert!(!range.is_alert(-100.0));
+
+        let range = Range::parse("10:").unwrap();
+        assert!(range.is_alert(9.0));
+        assert!(!range.is_alert(1000.0));
+
+        let range = Range::parse("@10:20").unwrap();
+        assert!(range.is_alert(15.0));
+        assert!(!range.is_alert(21.0));
+
+        assert!(Range::parse("20:10").is_err());
+        assert!(Range::parse("abc").is_err());
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds = Thresholds::parse("5", "50").unwrap();
+        assert_eq!(thresholds.check(3.0), ProblemType::OK);
+        assert_eq!(thresholds.check(6.0), ProblemType::WARNING);
+        assert_eq!(thresholds.check(51.0), ProblemType::CRITICAL);
+        assert_eq!(thresholds.perfdata(), ";5;50");
+        assert_eq!(Thresholds::default().perfdata(), "");
+    }
+}